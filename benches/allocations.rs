@@ -0,0 +1,96 @@
+extern crate rust_splay;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use rust_splay::{BitRange, Splay, SplayStrategy, SplayTree};
+use rust_splay::arena::{ArenaBitRange, ArenaSplayTree};
+
+// Counts every allocation made through the global allocator, so that we can
+// report allocations per operation alongside wall-clock time.
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const N: usize = 100_000;
+const OPS: usize = 100_000;
+
+fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+fn measure<F: FnMut()>(name: &str, ops: usize, mut f: F) {
+    let allocs_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    f();
+    let elapsed = start.elapsed();
+    let allocs = ALLOCATIONS.load(Ordering::Relaxed) - allocs_before;
+    println!("{:<40} {:>10.1} ns/op {:>10.2} allocs/op",
+             name,
+             elapsed.as_secs_f64() * 1e9 / ops as f64,
+             allocs as f64 / ops as f64);
+}
+
+fn insert_keys<T: Splay<u64>>(name: &str, mut tree: T) {
+    let mut state = 0x2545_f491_4f6c_dd1d;
+    measure(name, OPS, || {
+        for _ in 0..OPS {
+            tree.insert(xorshift(&mut state) % (N as u64));
+        }
+    });
+}
+
+fn reverse_ranges() -> Vec<(usize, usize)> {
+    let mut state = 0x9e37_79b9_7f4a_7c15;
+    (0..OPS).map(|_| {
+        let a = (xorshift(&mut state) % (N as u64 + 1)) as usize;
+        let b = (xorshift(&mut state) % (N as u64 + 1)) as usize;
+        (a.min(b), a.max(b))
+    }).collect()
+}
+
+fn reverse_boxed(name: &str, ranges: &[(usize, usize)], strategy: SplayStrategy) {
+    let mut range = BitRange::with_strategy(N, strategy);
+    measure(name, OPS, || {
+        for &(a, b) in ranges {
+            range.reverse_range(a, b);
+        }
+    });
+}
+
+fn main() {
+    insert_keys("SplayTree::insert (bottom-up)", SplayTree::with_strategy(SplayStrategy::BottomUp));
+    insert_keys("SplayTree::insert (top-down)", SplayTree::with_strategy(SplayStrategy::TopDown));
+    insert_keys("ArenaSplayTree::insert", ArenaSplayTree::new());
+
+    let ranges = reverse_ranges();
+    reverse_boxed("BitRange::reverse_range (bottom-up)", &ranges, SplayStrategy::BottomUp);
+    reverse_boxed("BitRange::reverse_range (top-down)", &ranges, SplayStrategy::TopDown);
+    let mut range = ArenaBitRange::new(N);
+    measure("ArenaBitRange::reverse_range", OPS, || {
+        for &(a, b) in &ranges {
+            range.reverse_range(a, b);
+        }
+    });
+}
@@ -0,0 +1,163 @@
+extern crate rust_splay;
+
+use std::collections::{BTreeSet, HashSet};
+use std::env;
+use std::fs::File;
+use std::hint::black_box;
+use std::io::BufWriter;
+use std::time::Instant;
+
+use rust_splay::{BitRange, Splay, SplayStrategy, SplayTree};
+use rust_splay::command::Command;
+use rust_splay::workload::{bit_commands, write_input, Distribution, Mix, Rng, Sampler};
+
+// Compares the splay trees against the standard collections. Each benchmark
+// runs its workload once to warm up and then SAMPLES more times on fresh
+// copies of the structure, and reports the fastest, median and slowest run
+// per operation in the style of criterion. Pass a name fragment to run only
+// the benchmarks whose names contain it, and set WORKLOAD_DIR to also write
+// the sequence workloads out in the driver's input format.
+
+const SAMPLES: usize = 10;
+const KEYS: u64 = 100_000;
+const LOOKUPS: usize = 100_000;
+const BITS: usize = 10_000;
+const COMMANDS: usize = 10_000;
+
+fn bench<S, F: FnMut() -> S, G: FnMut(S)>(filter: &Option<String>, name: &str, ops: usize, mut setup: F, mut run: G) {
+    if let Some(ref filter) = *filter {
+        if !name.contains(filter.as_str()) {
+            return;
+        }
+    }
+    run(setup());
+    let mut times: Vec<f64> = (0..SAMPLES).map(|_| {
+        let state = setup();
+        let start = Instant::now();
+        run(state);
+        start.elapsed().as_secs_f64() * 1e9 / ops as f64
+    }).collect();
+    times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    println!("{:<44} time: [{:>9.1} ns {:>9.1} ns {:>9.1} ns]",
+             name, times[0], times[SAMPLES / 2], times[SAMPLES - 1]);
+}
+
+fn lookups(distribution: Distribution) -> Vec<u64> {
+    let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+    // Look up both present (even) and absent (odd) keys.
+    let mut sampler = Sampler::new(2 * KEYS, distribution);
+    (0..LOOKUPS).map(|_| sampler.sample(&mut rng)).collect()
+}
+
+fn keys() -> Vec<u64> {
+    let mut rng = Rng::new(42);
+    let mut keys: Vec<u64> = (0..KEYS).map(|k| 2 * k).collect();
+    // Shuffle so that building the splay trees does not degenerate.
+    for i in (1..keys.len()).rev() {
+        let j = rng.below(i as u64 + 1) as usize;
+        keys.swap(i, j);
+    }
+    keys
+}
+
+fn splay_tree(keys: &[u64], strategy: SplayStrategy) -> SplayTree<u64> {
+    let mut tree = SplayTree::with_strategy(strategy);
+    for &k in keys {
+        tree.insert(k);
+    }
+    tree
+}
+
+fn bench_sets(filter: &Option<String>) {
+    let keys = keys();
+    let distributions = [
+        ("uniform", Distribution::Uniform),
+        ("zipf", Distribution::Zipf(1.0)),
+        ("sequential", Distribution::Sequential),
+    ];
+    for &(dist_name, distribution) in &distributions {
+        let queries = lookups(distribution);
+        for &(strategy_name, strategy) in &[("bottom-up", SplayStrategy::BottomUp), ("top-down", SplayStrategy::TopDown)] {
+            let name = format!("contains/{}/splay {}", dist_name, strategy_name);
+            bench(filter, &name, LOOKUPS, || splay_tree(&keys, strategy), |mut tree| {
+                for &q in &queries {
+                    black_box(tree.contains(q));
+                }
+            });
+        }
+        bench(filter, &format!("contains/{}/btreeset", dist_name), LOOKUPS, || {
+            keys.iter().cloned().collect::<BTreeSet<u64>>()
+        }, |set| {
+            for q in &queries {
+                black_box(set.contains(q));
+            }
+        });
+        bench(filter, &format!("contains/{}/hashset", dist_name), LOOKUPS, || {
+            keys.iter().cloned().collect::<HashSet<u64>>()
+        }, |set| {
+            for q in &queries {
+                black_box(set.contains(q));
+            }
+        });
+    }
+}
+
+fn run_vec(bits: &mut [bool], commands: &[Command]) {
+    for &command in commands {
+        match command {
+            Command::Set(i, val) => bits[i] = val,
+            Command::Get(i) => {
+                black_box(bits[i]);
+            },
+            Command::Reverse(start, end) => bits[start..end].reverse(),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn run_bit_range(range: &mut BitRange, commands: &[Command]) {
+    for &command in commands {
+        match command {
+            Command::Set(i, val) => range.set(i, val),
+            Command::Get(i) => {
+                black_box(range.get(i));
+            },
+            Command::Reverse(start, end) => range.reverse_range(start, end),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn bench_sequences(filter: &Option<String>) {
+    let mixes = [
+        ("reverse-heavy", Mix { set: 1, get: 1, reverse: 8, ..Mix::default() }),
+        ("balanced", Mix { set: 1, get: 1, reverse: 1, ..Mix::default() }),
+        ("read-heavy", Mix { set: 1, get: 8, reverse: 1, ..Mix::default() }),
+    ];
+    for &(mix_name, mix) in &mixes {
+        let mut rng = Rng::new(7);
+        let mut indices = Sampler::new(BITS as u64, Distribution::Uniform);
+        let commands = bit_commands(&mut rng, BITS, COMMANDS, mix, &mut indices);
+        if let Ok(dir) = env::var("WORKLOAD_DIR") {
+            let path = format!("{}/range_reverse.{}.in", dir, mix_name);
+            let mut out = BufWriter::new(File::create(&path).expect("could not create workload file"));
+            write_input(&mut out, BITS, &commands).expect("could not write workload file");
+        }
+        for &(strategy_name, strategy) in &[("bottom-up", SplayStrategy::BottomUp), ("top-down", SplayStrategy::TopDown)] {
+            let name = format!("sequence/{}/bitrange {}", mix_name, strategy_name);
+            bench(filter, &name, COMMANDS, || BitRange::with_strategy(BITS, strategy), |mut range| {
+                run_bit_range(&mut range, &commands);
+            });
+        }
+        bench(filter, &format!("sequence/{}/vec", mix_name), COMMANDS, || vec![false; BITS], |mut bits| {
+            run_vec(&mut bits, &commands);
+        });
+    }
+}
+
+fn main() {
+    // `cargo bench` passes --bench; anything else is a name filter.
+    let filter = env::args().skip(1).find(|arg| !arg.starts_with("--"));
+    bench_sets(&filter);
+    bench_sequences(&filter);
+}
@@ -0,0 +1,388 @@
+use std::cmp::Ordering;
+use std::mem;
+
+use Splay;
+
+// Arena-backed splay trees keep every node in a single `Vec` and refer to
+// children by `u32` index instead of by `Box`. Splaying relinks indices in
+// place, so once the arena has grown to size an operation does not allocate:
+// the access path is recorded in a scratch buffer that is reused between
+// operations, and slots freed by removals are recycled through a free list.
+// An arena holds fewer than `u32::MAX` nodes, which also keeps subtree sizes
+// within a `u32`.
+
+const NIL: u32 = u32::MAX;
+
+#[derive(Debug)]
+struct ArenaNode<A> {
+    // None while the slot is on the free list.
+    val: Option<A>,
+    size: u32,
+    reversed: bool,
+    left: u32,
+    right: u32,
+}
+
+#[derive(Debug)]
+struct Arena<A> {
+    nodes: Vec<ArenaNode<A>>,
+    free: Vec<u32>,
+    path: Vec<u32>,
+    root: u32,
+}
+
+impl<A> Arena<A> {
+    fn new() -> Arena<A> {
+        Arena {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            path: Vec::new(),
+            root: NIL,
+        }
+    }
+
+    fn alloc(&mut self, val: A) -> u32 {
+        let node = ArenaNode {
+            val: Some(val),
+            size: 1,
+            reversed: false,
+            left: NIL,
+            right: NIL,
+        };
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i as usize] = node;
+                i
+            },
+            None => {
+                assert!(self.nodes.len() < NIL as usize, "arena is full");
+                self.nodes.push(node);
+                (self.nodes.len() - 1) as u32
+            },
+        }
+    }
+
+    // Frees slot `i`, dropping its value.
+    fn release(&mut self, i: u32) {
+        self.nodes[i as usize].val = None;
+        self.free.push(i);
+    }
+
+    fn val(&self, i: u32) -> &A {
+        self.nodes[i as usize].val.as_ref().expect("freed arena slot")
+    }
+
+    fn size(&self, i: u32) -> usize {
+        if i == NIL { 0 } else { self.nodes[i as usize].size as usize }
+    }
+
+    fn update(&mut self, i: u32) {
+        let size = self.size(self.nodes[i as usize].left) + self.size(self.nodes[i as usize].right) + 1;
+        self.nodes[i as usize].size = size as u32;
+    }
+
+    fn toggle(&mut self, i: u32) {
+        if i != NIL {
+            self.nodes[i as usize].reversed = !self.nodes[i as usize].reversed;
+        }
+    }
+
+    // Applies a pending reversal to the children of `i`.
+    fn push(&mut self, i: u32) {
+        if self.nodes[i as usize].reversed {
+            let node = &mut self.nodes[i as usize];
+            node.reversed = false;
+            mem::swap(&mut node.left, &mut node.right);
+            let (left, right) = (node.left, node.right);
+            self.toggle(left);
+            self.toggle(right);
+        }
+    }
+
+    // Rotates `x` above its parent `p`. The caller is responsible for
+    // pointing p's old parent at `x`.
+    fn rotate_up(&mut self, x: u32, p: u32) {
+        if self.nodes[p as usize].left == x {
+            self.nodes[p as usize].left = self.nodes[x as usize].right;
+            self.nodes[x as usize].right = p;
+        } else {
+            self.nodes[p as usize].right = self.nodes[x as usize].left;
+            self.nodes[x as usize].left = p;
+        }
+        self.update(p);
+        self.update(x);
+    }
+
+    fn replace_child(&mut self, parent: u32, old: u32, new: u32) {
+        if self.nodes[parent as usize].left == old {
+            self.nodes[parent as usize].left = new;
+        } else {
+            self.nodes[parent as usize].right = new;
+        }
+    }
+
+    // Splays the last node of the recorded path to the position of the first
+    // one and returns it. Every node on the path must already be pushed.
+    fn splay_path(&mut self) -> u32 {
+        let path = mem::take(&mut self.path);
+        let mut k = path.len() - 1;
+        let x = path[k];
+        while k >= 2 {
+            let p = path[k - 1];
+            let g = path[k - 2];
+            let zig_zig = (self.nodes[g as usize].left == p) == (self.nodes[p as usize].left == x);
+            if zig_zig {
+                self.rotate_up(p, g);
+                self.rotate_up(x, p);
+            } else {
+                self.rotate_up(x, p);
+                self.replace_child(g, p, x);
+                self.rotate_up(x, g);
+            }
+            k -= 2;
+            if k >= 1 {
+                self.replace_child(path[k - 1], g, x);
+            }
+        }
+        if k == 1 {
+            self.rotate_up(x, path[0]);
+        }
+        self.path = path;
+        x
+    }
+
+    // Records the path from `start` to the node at `index` within its
+    // subtree, or to the last node visited if there is none.
+    fn descend_index(&mut self, start: u32, index: usize) -> bool {
+        self.path.clear();
+        let mut node = start;
+        let mut remaining = index;
+        while node != NIL {
+            self.push(node);
+            self.path.push(node);
+            let left = self.nodes[node as usize].left;
+            let left_size = self.size(left);
+            match left_size.cmp(&remaining) {
+                Ordering::Less => {
+                    remaining = remaining - left_size - 1;
+                    node = self.nodes[node as usize].right;
+                },
+                Ordering::Equal => return true,
+                Ordering::Greater => node = left,
+            }
+        }
+        false
+    }
+
+    fn splay_index(&mut self, index: usize) -> bool {
+        let root = self.root;
+        let found = self.descend_index(root, index);
+        if !self.path.is_empty() {
+            self.root = self.splay_path();
+        }
+        found
+    }
+
+    fn build(&mut self, n: usize, val: &A) -> u32 where A: Clone {
+        if n == 0 {
+            return NIL;
+        }
+        let left = self.build(n / 2, val);
+        let node = self.alloc(val.clone());
+        let right = self.build(n - n / 2 - 1, val);
+        self.nodes[node as usize].left = left;
+        self.nodes[node as usize].right = right;
+        self.update(node);
+        node
+    }
+}
+
+impl<A: Ord> Arena<A> {
+    // Records the path from the root to the node holding `v`, or to the last
+    // node visited if `v` is absent.
+    fn descend_key(&mut self, v: &A) -> Option<Ordering> {
+        self.path.clear();
+        let mut node = self.root;
+        let mut last = None;
+        while node != NIL {
+            self.path.push(node);
+            let ord = v.cmp(self.val(node));
+            last = Some(ord);
+            node = match ord {
+                Ordering::Less => self.nodes[node as usize].left,
+                Ordering::Equal => break,
+                Ordering::Greater => self.nodes[node as usize].right,
+            };
+        }
+        last
+    }
+}
+
+#[derive(Debug)]
+pub struct ArenaSplayTree<A> {
+    arena: Arena<A>,
+}
+
+impl<A: Ord> ArenaSplayTree<A> {
+    pub fn len(&self) -> usize {
+        self.arena.size(self.arena.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arena.root == NIL
+    }
+
+    pub fn remove(&mut self, v: &A) -> bool {
+        if self.arena.descend_key(v) != Some(Ordering::Equal) {
+            if !self.arena.path.is_empty() {
+                self.arena.root = self.arena.splay_path();
+            }
+            return false;
+        }
+        let old_root = self.arena.splay_path();
+        let left = self.arena.nodes[old_root as usize].left;
+        let right = self.arena.nodes[old_root as usize].right;
+        self.arena.release(old_root);
+        if left == NIL {
+            self.arena.root = right;
+            return true;
+        }
+        self.arena.path.clear();
+        let mut node = left;
+        while node != NIL {
+            self.arena.path.push(node);
+            node = self.arena.nodes[node as usize].right;
+        }
+        let new_root = self.arena.splay_path();
+        self.arena.nodes[new_root as usize].right = right;
+        self.arena.update(new_root);
+        self.arena.root = new_root;
+        true
+    }
+}
+
+impl<A: Ord> Splay<A> for ArenaSplayTree<A> {
+    fn new() -> ArenaSplayTree<A> {
+        ArenaSplayTree {
+            arena: Arena::new(),
+        }
+    }
+
+    fn insert(&mut self, v: A) {
+        match self.arena.descend_key(&v) {
+            None => {
+                self.arena.root = self.arena.alloc(v);
+                return;
+            },
+            Some(Ordering::Equal) => {},
+            Some(ord) => {
+                let parent = *self.arena.path.last().unwrap();
+                let node = self.arena.alloc(v);
+                if ord == Ordering::Less {
+                    self.arena.nodes[parent as usize].left = node;
+                } else {
+                    self.arena.nodes[parent as usize].right = node;
+                }
+                for &i in &self.arena.path {
+                    self.arena.nodes[i as usize].size += 1;
+                }
+                self.arena.path.push(node);
+            },
+        }
+        self.arena.root = self.arena.splay_path();
+    }
+
+    fn contains(&mut self, v: A) -> bool {
+        let found = self.arena.descend_key(&v) == Some(Ordering::Equal);
+        if !self.arena.path.is_empty() {
+            self.arena.root = self.arena.splay_path();
+        }
+        found
+    }
+
+    fn splay_to_root(&mut self, v: A) {
+        self.contains(v);
+    }
+}
+
+#[derive(Debug)]
+pub struct ArenaBitRange {
+    arena: Arena<bool>,
+}
+
+impl ArenaBitRange {
+    pub fn new(n: usize) -> ArenaBitRange {
+        let mut arena = Arena::new();
+        arena.root = arena.build(n, &false);
+        ArenaBitRange {
+            arena: arena,
+        }
+    }
+
+    pub fn set(self: &mut ArenaBitRange, index: usize, val: bool) {
+        if self.arena.splay_index(index) {
+            let root = self.arena.root;
+            self.arena.nodes[root as usize].val = Some(val);
+        }
+    }
+
+    pub fn get(self: &mut ArenaBitRange, index: usize) -> Option<bool> {
+        if self.arena.splay_index(index) {
+            Some(*self.arena.val(self.arena.root))
+        } else {
+            None
+        }
+    }
+
+    pub fn reverse_range(self: &mut ArenaBitRange, index_start: usize, index_end: usize) {
+        let len = self.arena.size(self.arena.root);
+        let index_end = index_end.min(len);
+        if index_start >= index_end {
+            return;
+        }
+        if index_start == 0 {
+            if index_end == len {
+                let root = self.arena.root;
+                self.arena.toggle(root);
+            } else {
+                self.arena.splay_index(index_end);
+                let left = self.arena.nodes[self.arena.root as usize].left;
+                self.arena.toggle(left);
+            }
+        } else {
+            self.arena.splay_index(index_start - 1);
+            let root = self.arena.root;
+            if index_end == len {
+                let right = self.arena.nodes[root as usize].right;
+                self.arena.toggle(right);
+            } else {
+                let right = self.arena.nodes[root as usize].right;
+                self.arena.descend_index(right, index_end - index_start);
+                let right = self.arena.splay_path();
+                self.arena.nodes[root as usize].right = right;
+                let middle = self.arena.nodes[right as usize].left;
+                self.arena.toggle(middle);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+
+    #[test]
+    fn removal_drops_the_value() {
+        let mut tree = ArenaSplayTree::new();
+        let shared = Rc::new(5);
+        for v in 0..10 {
+            tree.insert(Rc::new(v * 2));
+        }
+        tree.insert(shared.clone());
+        assert_eq!(Rc::strong_count(&shared), 2);
+        assert!(tree.remove(&shared));
+        assert_eq!(Rc::strong_count(&shared), 1);
+        assert_eq!(tree.len(), 10);
+    }
+}
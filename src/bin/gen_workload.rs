@@ -0,0 +1,166 @@
+#![allow(clippy::redundant_field_names)]
+
+extern crate rust_splay;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+use rust_splay::command::COMMAND_LETTERS;
+use rust_splay::workload::{bit_commands, write_expected, write_input, Distribution, Mix, Rng, Sampler};
+
+// Generates random input for the driver, and optionally the output the driver
+// should produce for it, computed with a plain `Vec<bool>`. Together they make
+// an end-to-end regression test:
+//
+//     gen_workload -n 1000 -m 5000 --expected case.out case.in
+//     rust_splay case.in | diff - case.out
+
+const USAGE: &str = "usage: gen_workload [-n LENGTH] [-m COMMANDS] [--seed SEED] [--mix MIX]
+                    [--indices DISTRIBUTION] [--expected FILE] [OUTPUT]
+Writes LENGTH (default 1000) and COMMANDS (default 1000) random commands to
+OUTPUT, or standard output. MIX gives command weights as LETTER=WEIGHT pairs
+separated by commas, by default S=1,G=1,R=1. DISTRIBUTION is uniform (the
+default), sequential or zipf, optionally followed by an exponent as in
+zipf:1.2. With --expected, the expected driver output is written to FILE.";
+
+#[derive(Debug)]
+struct Options {
+    n: usize,
+    m: usize,
+    seed: u64,
+    mix: Mix,
+    indices: Distribution,
+    expected: Option<String>,
+    output: Option<String>,
+}
+
+fn parse_number<T: std::str::FromStr>(what: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid {} `{}`", what, value))
+}
+
+fn parse_mix(value: &str) -> Result<Mix, String> {
+    let mut mix = Mix::default();
+    for part in value.split(',') {
+        let mut halves = part.splitn(2, '=');
+        let letter = halves.next().unwrap_or("");
+        let weight = halves.next().ok_or_else(|| format!("expected LETTER=WEIGHT, got `{}`", part))?;
+        let weight = parse_number("weight", weight)?;
+        let mut chars = letter.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if mix.set_weight(c, weight) => {},
+            _ => return Err(format!("unknown command `{}`, expected one of {}", letter, COMMAND_LETTERS)),
+        }
+    }
+    if mix == Mix::default() {
+        return Err(String::from("the command mix has no positive weights"));
+    }
+    Ok(mix)
+}
+
+fn parse_distribution(value: &str) -> Result<Distribution, String> {
+    let mut parts = value.splitn(2, ':');
+    match (parts.next().unwrap_or(""), parts.next()) {
+        ("uniform", None) => Ok(Distribution::Uniform),
+        ("sequential", None) => Ok(Distribution::Sequential),
+        ("zipf", None) => Ok(Distribution::Zipf(1.0)),
+        ("zipf", Some(s)) => Ok(Distribution::Zipf(parse_number("exponent", s)?)),
+        _ => Err(format!("unknown distribution `{}`", value)),
+    }
+}
+
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options {
+        n: 1000,
+        m: 1000,
+        seed: 1,
+        mix: Mix { set: 1, get: 1, reverse: 1, ..Mix::default() },
+        indices: Distribution::Uniform,
+        expected: None,
+        output: None,
+    };
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("missing value for `{}`", arg));
+        match arg.as_str() {
+            "-n" => options.n = parse_number("length", &value()?)?,
+            "-m" => options.m = parse_number("command count", &value()?)?,
+            "--seed" => options.seed = parse_number("seed", &value()?)?,
+            "--mix" => options.mix = parse_mix(&value()?)?,
+            "--indices" => options.indices = parse_distribution(&value()?)?,
+            "--expected" => options.expected = Some(value()?),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unrecognized option `{}`", arg)),
+            _ if options.output.is_none() => options.output = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn create(path: &Option<String>) -> io::Result<Box<dyn Write>> {
+    match *path {
+        Some(ref path) if path != "-" => Ok(Box::new(BufWriter::new(File::create(path)?))),
+        _ => Ok(Box::new(BufWriter::new(io::stdout()))),
+    }
+}
+
+fn run(options: Options) -> io::Result<()> {
+    let mut rng = Rng::new(options.seed);
+    // Sample from the initial length; positions are reduced modulo the
+    // current length as the sequence grows and shrinks.
+    let mut indices = Sampler::new(options.n.max(1) as u64, options.indices);
+    let commands = bit_commands(&mut rng, options.n, options.m, options.mix, &mut indices);
+    let mut out = create(&options.output)?;
+    write_input(&mut out, options.n, &commands)?;
+    out.flush()?;
+    if let Some(ref path) = options.expected {
+        let mut expected = BufWriter::new(File::create(path)?);
+        write_expected(&mut expected, options.n, &commands)?;
+        expected.flush()?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(why) => {
+            eprintln!("error: {}\n{}", why, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(why) = run(options) {
+        eprintln!("error: {}", why);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = args(&["-n", "50", "--mix", "R=3,M=1", "--indices", "zipf:1.5", "--seed", "9", "out.in"]).unwrap();
+        assert_eq!((options.n, options.m, options.seed), (50, 1000, 9));
+        assert_eq!(options.mix, Mix { reverse: 3, moves: 1, ..Mix::default() });
+        assert_eq!(options.indices, Distribution::Zipf(1.5));
+        assert_eq!(options.output, Some("out.in".to_string()));
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        assert!(args(&["--mix", "X=1"]).is_err());
+        assert!(args(&["--mix", "R=0"]).is_err());
+        assert!(args(&["--mix", "R"]).is_err());
+        assert!(args(&["--indices", "normal"]).is_err());
+        assert!(args(&["-n", "-4"]).is_err());
+        assert!(args(&["-m"]).is_err());
+        assert!(args(&["a", "b"]).is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use std::error::Error;
+use std::fmt;
+use std::num::ParseIntError;
+
+// The text protocol understood by the driver. Input starts with a header line
+// followed by one command per line:
+//
+//     header  := LENGTH COUNT
+//     command := "S" INDEX BIT          set the bit at INDEX
+//              | "G" INDEX              print the bit at INDEX
+//              | "R" START END          reverse [START, END)
+//              | "I" INDEX BIT          insert BIT so that it lands at INDEX
+//              | "D" INDEX              delete the bit at INDEX
+//              | "C" START END          print the number of set bits in [START, END)
+//              | "F" START END          flip every bit in [START, END)
+//              | "A" START END BIT      assign BIT to every bit in [START, END)
+//              | "P"                    print the whole sequence as 0s and 1s
+//              | "M" START END TO       move [START, END) so that it starts at TO
+//                                       of the remaining bits
+//
+// LENGTH, INDEX, START, END and TO are non-negative decimal integers, BIT is
+// 0 or 1 and COUNT is the number of command lines that follow. Tokens are
+// separated by whitespace and anything after the expected tokens is ignored.
+// Ranges are half-open and are clamped to the sequence, but an INDEX must
+// name an existing bit (or, for "I", the end of the sequence): the driver
+// stops with an error at the first S, G, I or D that is out of range.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Set(usize, bool),
+    Get(usize),
+    Reverse(usize, usize),
+    Insert(usize, bool),
+    Delete(usize),
+    Count(usize, usize),
+    Flip(usize, usize),
+    Fill(usize, usize, bool),
+    Print,
+    Move(usize, usize, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub length: usize,
+    pub commands: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    MissingToken(&'static str),
+    InvalidNumber(String, ParseIntError),
+    InvalidBit(String),
+    UnknownCommand(String),
+}
+
+// A parse error together with the 1-based column it refers to. A missing
+// token points just past the end of the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub column: usize,
+    pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseErrorKind::MissingToken(what) => write!(f, "missing {}", what),
+            ParseErrorKind::InvalidNumber(ref token, ref why) => write!(f, "invalid number `{}`: {}", token, why),
+            ParseErrorKind::InvalidBit(ref token) => write!(f, "invalid bit `{}`, expected 0 or 1", token),
+            ParseErrorKind::UnknownCommand(ref token) => write!(f, "unknown command `{}`", token),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column, self.kind)
+    }
+}
+
+impl Error for ParseError {}
+
+impl Command {
+    // The letter that introduces the command.
+    pub fn letter(&self) -> char {
+        match *self {
+            Command::Set(..) => 'S',
+            Command::Get(..) => 'G',
+            Command::Reverse(..) => 'R',
+            Command::Insert(..) => 'I',
+            Command::Delete(..) => 'D',
+            Command::Count(..) => 'C',
+            Command::Flip(..) => 'F',
+            Command::Fill(..) => 'A',
+            Command::Print => 'P',
+            Command::Move(..) => 'M',
+        }
+    }
+}
+
+pub const COMMAND_LETTERS: &str = "SGRIDCFAPM";
+
+fn bit_digit(bit: bool) -> u8 {
+    if bit { 1 } else { 0 }
+}
+
+// Writes a command back out in the form `parse_command` accepts.
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Command::Set(index, bit) => write!(f, "S {} {}", index, bit_digit(bit)),
+            Command::Get(index) => write!(f, "G {}", index),
+            Command::Reverse(start, end) => write!(f, "R {} {}", start, end),
+            Command::Insert(index, bit) => write!(f, "I {} {}", index, bit_digit(bit)),
+            Command::Delete(index) => write!(f, "D {}", index),
+            Command::Count(start, end) => write!(f, "C {} {}", start, end),
+            Command::Flip(start, end) => write!(f, "F {} {}", start, end),
+            Command::Fill(start, end, bit) => write!(f, "A {} {} {}", start, end, bit_digit(bit)),
+            Command::Print => write!(f, "P"),
+            Command::Move(start, end, to) => write!(f, "M {} {} {}", start, end, to),
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.length, self.commands)
+    }
+}
+
+// A single line of input, split into whitespace separated tokens that
+// remember their 1-based column for error messages.
+struct Line<'a> {
+    tokens: Vec<(usize, &'a str)>,
+    end_column: usize,
+}
+
+impl<'a> Line<'a> {
+    fn new(text: &'a str) -> Line<'a> {
+        let mut tokens = Vec::new();
+        let mut start = None;
+        for (i, c) in text.char_indices() {
+            match (c.is_whitespace(), start) {
+                (false, None) => start = Some(i),
+                (true, Some(s)) => {
+                    tokens.push((s + 1, &text[s..i]));
+                    start = None;
+                },
+                _ => {},
+            }
+        }
+        if let Some(s) = start {
+            tokens.push((s + 1, &text[s..]));
+        }
+        Line {
+            tokens: tokens,
+            end_column: text.trim_end().len() + 1,
+        }
+    }
+
+    fn token(&self, i: usize, what: &'static str) -> Result<(usize, &'a str), ParseError> {
+        match self.tokens.get(i) {
+            Some(&token) => Ok(token),
+            None => Err(ParseError {
+                column: self.end_column,
+                kind: ParseErrorKind::MissingToken(what),
+            }),
+        }
+    }
+
+    fn number(&self, i: usize, what: &'static str) -> Result<usize, ParseError> {
+        let (column, token) = self.token(i, what)?;
+        token.parse::<usize>().map_err(|why| ParseError {
+            column: column,
+            kind: ParseErrorKind::InvalidNumber(token.to_string(), why),
+        })
+    }
+
+    fn bit(&self, i: usize, what: &'static str) -> Result<bool, ParseError> {
+        let (column, token) = self.token(i, what)?;
+        match token {
+            "0" => Ok(false),
+            "1" => Ok(true),
+            _ => Err(ParseError {
+                column: column,
+                kind: ParseErrorKind::InvalidBit(token.to_string()),
+            }),
+        }
+    }
+}
+
+pub fn parse_header(text: &str) -> Result<Header, ParseError> {
+    let line = Line::new(text);
+    let length = line.number(0, "sequence length")?;
+    let count = line.number(1, "command count")?;
+    Ok(Header {
+        length: length,
+        commands: count,
+    })
+}
+
+pub fn parse_command(text: &str) -> Result<Command, ParseError> {
+    let line = Line::new(text);
+    let (column, name) = line.token(0, "command")?;
+    let command = match name {
+        "S" => Command::Set(line.number(1, "index")?, line.bit(2, "bit")?),
+        "G" => Command::Get(line.number(1, "index")?),
+        "R" => Command::Reverse(line.number(1, "start index")?, line.number(2, "end index")?),
+        "I" => Command::Insert(line.number(1, "index")?, line.bit(2, "bit")?),
+        "D" => Command::Delete(line.number(1, "index")?),
+        "C" => Command::Count(line.number(1, "start index")?, line.number(2, "end index")?),
+        "F" => Command::Flip(line.number(1, "start index")?, line.number(2, "end index")?),
+        "A" => Command::Fill(line.number(1, "start index")?, line.number(2, "end index")?, line.bit(3, "bit")?),
+        "P" => Command::Print,
+        "M" => Command::Move(line.number(1, "start index")?, line.number(2, "end index")?, line.number(3, "target index")?),
+        _ => {
+            return Err(ParseError {
+                column: column,
+                kind: ParseErrorKind::UnknownCommand(name.to_string()),
+            });
+        },
+    };
+    Ok(command)
+}
+
+// The column a command's argument starts at, for pointing errors that are
+// only detected when the command runs (such as an index out of range) back at
+// the offending token.
+pub fn argument_column(text: &str, argument: usize) -> usize {
+    let line = Line::new(text);
+    match line.tokens.get(argument + 1) {
+        Some(&(column, _)) => column,
+        None => line.end_column,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_column(result: Result<Command, ParseError>) -> usize {
+        result.unwrap_err().column
+    }
+
+    #[test]
+    fn tokens_remember_columns() {
+        let line = Line::new("R  12\t7\n");
+        assert_eq!(line.tokens, vec![(1, "R"), (4, "12"), (7, "7")]);
+        assert_eq!(parse_command("R  12\t7\n").unwrap(), Command::Reverse(12, 7));
+        assert_eq!(argument_column("R  12\t7\n", 1), 7);
+    }
+
+    #[test]
+    fn parses_every_command() {
+        let commands = [
+            Command::Set(1, true),
+            Command::Get(2),
+            Command::Reverse(0, 3),
+            Command::Insert(4, false),
+            Command::Delete(0),
+            Command::Count(1, 5),
+            Command::Flip(2, 2),
+            Command::Fill(0, 9, true),
+            Command::Print,
+            Command::Move(1, 3, 0),
+        ];
+        for &command in &commands {
+            assert_eq!(parse_command(&command.to_string()).unwrap(), command);
+            assert!(command.to_string().starts_with(command.letter()));
+        }
+    }
+
+    #[test]
+    fn parses_header() {
+        let header = parse_header("5 6\n").unwrap();
+        assert_eq!(header, Header { length: 5, commands: 6 });
+        let error = parse_header("5 -1").unwrap_err();
+        assert_eq!(error.column, 3);
+        assert!(matches!(error.kind, ParseErrorKind::InvalidNumber(ref token, _) if token == "-1"));
+        assert!(matches!(parse_command("G -2").unwrap_err().kind, ParseErrorKind::InvalidNumber(..)));
+    }
+
+    #[test]
+    fn missing_token_points_past_the_end() {
+        assert_eq!(error_column(parse_command("S 1\n")), 4);
+        assert_eq!(error_column(parse_command("A 1 2 ")), 6);
+        assert_eq!(error_column(parse_command("")), 1);
+    }
+
+    #[test]
+    fn invalid_tokens_point_at_the_token() {
+        assert_eq!(error_column(parse_command("S x 2")), 3);
+        assert_eq!(error_column(parse_command("S 1 2")), 5);
+        let error = parse_command("  X 1").unwrap_err();
+        assert_eq!(error.column, 3);
+        assert_eq!(error.kind, ParseErrorKind::UnknownCommand("X".to_string()));
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt::{self, Debug, Display, Write};
+
+use {TreeF, TreeNode, AnnotatedTreeNode, BitOp, BitRangeNode};
+
+// Graphviz export of tree shapes. Every non-empty node becomes a box labelled
+// with its value and whatever the node caches, and edges are labelled L and R so
+// that the shape survives Graphviz's layout. An empty child next to a
+// non-empty one is drawn as a point, otherwise a lone child could not be told
+// apart from its missing sibling. Children are shown as stored: a subtree
+// with a pending reversal is drawn dashed and in red, and its children have
+// not been swapped yet.
+//
+// The output is a complete `digraph`, ready for `dot -Tsvg`.
+
+struct Style {
+    label: String,
+    reversed: bool,
+}
+
+type Children<'a, N> = Option<(&'a N, &'a N)>;
+
+fn write_dot<'a, N, C, S>(root: &'a N, children: C, style: S) -> String
+    where C: Fn(&'a N) -> Children<'a, N>, S: Fn(&N) -> Style {
+    let mut out = String::from("digraph tree {\n    node [shape=box, fontname=\"monospace\"];\n");
+    let mut next_id = 1;
+    // Splay trees can be arbitrarily deep, so keep an explicit stack of nodes
+    // to draw together with the ids they were given.
+    let mut stack = vec![(root, 0)];
+    while let Some((node, id)) = stack.pop() {
+        let (left, right) = match children(node) {
+            Some(pair) => pair,
+            None => continue,
+        };
+        let style = style(node);
+        let _ = write!(out, "    n{} [label=\"{}\"", id, escape(&style.label));
+        if style.reversed {
+            out.push_str(", style=dashed, color=red");
+        }
+        out.push_str("];\n");
+        if children(left).is_none() && children(right).is_none() {
+            continue;
+        }
+        for &(child, side) in &[(left, "L"), (right, "R")] {
+            let child_id = next_id;
+            next_id += 1;
+            if children(child).is_some() {
+                stack.push((child, child_id));
+            } else {
+                let _ = writeln!(out, "    n{} [shape=point];", child_id);
+            }
+            let _ = writeln!(out, "    n{} -> n{} [label=\"{}\"];", id, child_id, side);
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+// Quotes a label for use inside a DOT string, keeping line breaks.
+fn escape(label: &str) -> String {
+    let mut out = String::with_capacity(label.len());
+    for c in label.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn plain(label: String) -> Style {
+    Style {
+        label: label,
+        reversed: false,
+    }
+}
+
+fn tree_children<A>(node: &TreeNode<A>) -> Children<'_, TreeNode<A>> {
+    match *node {
+        TreeNode(TreeF::Empty) => None,
+        TreeNode(TreeF::Branch { ref left, ref right, .. }) => Some((&**left, &**right)),
+    }
+}
+
+fn annotated_children<A, B>(node: &AnnotatedTreeNode<A, B>) -> Children<'_, AnnotatedTreeNode<A, B>> {
+    match node.node {
+        TreeF::Empty => None,
+        TreeF::Branch { ref left, ref right, .. } => Some((&**left, &**right)),
+    }
+}
+
+fn bit_range_children(node: &BitRangeNode) -> Children<'_, BitRangeNode> {
+    match *node {
+        BitRangeNode::Empty => None,
+        BitRangeNode::Branch { ref left, ref right, .. } => Some((&**left, &**right)),
+    }
+}
+
+impl<A: Display> TreeNode<A> {
+    pub fn to_dot(&self) -> String {
+        write_dot(self, tree_children, |node| {
+            match *node {
+                TreeNode(TreeF::Branch { ref val, .. }) => plain(val.to_string()),
+                TreeNode(TreeF::Empty) => plain(String::new()),
+            }
+        })
+    }
+}
+
+impl<A: Display, B: Debug> AnnotatedTreeNode<A, B> {
+    pub fn to_dot(&self) -> String {
+        write_dot(self, annotated_children, |node| {
+            match node.node {
+                TreeF::Branch { ref val, .. } => plain(format!("{}\n{:?}", val, node.annotation)),
+                TreeF::Empty => plain(String::new()),
+            }
+        })
+    }
+}
+
+impl fmt::Display for BitOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BitOp::Keep => write!(f, "keep"),
+            BitOp::Flip => write!(f, "flip"),
+            BitOp::Fill(b) => write!(f, "fill {}", b as u8),
+        }
+    }
+}
+
+impl BitRangeNode {
+    pub fn to_dot(&self) -> String {
+        write_dot(self, bit_range_children, |node| {
+            match *node {
+                BitRangeNode::Branch { here, size, ones, reversed, pending, .. } => {
+                    let mut label = format!("{}\nsize {}, ones {}", here as u8, size, ones);
+                    if reversed {
+                        label.push_str("\nreversed");
+                    }
+                    if pending != BitOp::Keep {
+                        let _ = write!(label, "\npending {}", pending);
+                    }
+                    Style {
+                        label: label,
+                        reversed: reversed,
+                    }
+                },
+                BitRangeNode::Empty => plain(String::new()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {TreeAlgebra, Reversible};
+    use super::*;
+
+    fn node(val: i32, left: TreeNode<i32>, right: TreeNode<i32>) -> TreeNode<i32> {
+        TreeAlgebra::combine(TreeF::Branch {
+            val: val,
+            left: left,
+            right: right,
+        })
+    }
+
+    #[test]
+    fn draws_points_for_missing_siblings() {
+        let leaf = node(1, TreeNode(TreeF::Empty), TreeNode(TreeF::Empty));
+        let tree = node(2, leaf, TreeNode(TreeF::Empty));
+        assert_eq!(tree.to_dot(), "digraph tree {
+    node [shape=box, fontname=\"monospace\"];
+    n0 [label=\"2\"];
+    n0 -> n1 [label=\"L\"];
+    n2 [shape=point];
+    n0 -> n2 [label=\"R\"];
+    n1 [label=\"1\"];
+}
+");
+        assert_eq!(TreeNode::<i32>(TreeF::Empty).to_dot(), "digraph tree {\n    node [shape=box, fontname=\"monospace\"];\n}\n");
+    }
+
+    #[test]
+    fn marks_pending_work_on_bit_ranges() {
+        let leaf: BitRangeNode = TreeAlgebra::combine(TreeF::Branch {
+            val: true,
+            left: BitRangeNode::Empty,
+            right: BitRangeNode::Empty,
+        });
+        let dot = BitRangeNode::updated(Reversible::reversed(leaf), BitOp::Flip).to_dot();
+        assert!(dot.contains("n0 [label=\"0\\nsize 1, ones 0\\nreversed\\npending flip\", style=dashed, color=red];"), "{}", dot);
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(escape("a \"b\"\\\nc"), "a \\\"b\\\"\\\\\\nc");
+    }
+}
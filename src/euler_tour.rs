@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::mem;
+
+// Euler-tour trees: each tree of a forest is kept as the sequence of edges an
+// Euler tour of it traverses, with every vertex appearing once as a loop at
+// its first visit. The sequence is an implicit splay tree, as in `BitRange`,
+// with nodes counting both their subtree's size and the vertices in it.
+// Linking, cutting and rerooting a tree only cut sequences apart and join
+// them back together in another order, so each takes amortized O(log n), as
+// do connectivity and subtree-size queries.
+//
+// Operations start from a given vertex or edge rather than from an index, so
+// nodes need to find their way up to the root. They live in an arena with
+// parent links, as in `link_cut`.
+
+const NIL: usize = usize::MAX;
+
+#[derive(Debug)]
+struct TourNode {
+    size: usize,
+    vertices: usize,
+    is_vertex: bool,
+    left: usize,
+    right: usize,
+    parent: usize,
+}
+
+#[derive(Debug)]
+pub struct EulerTourForest {
+    nodes: Vec<TourNode>,
+    free: Vec<usize>,
+    // The node of each vertex, and of each directed edge.
+    vertices: Vec<usize>,
+    edges: HashMap<(usize, usize), usize>,
+}
+
+impl EulerTourForest {
+    // A forest of `n` vertices and no edges.
+    pub fn new(n: usize) -> EulerTourForest {
+        let mut forest = EulerTourForest {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            vertices: Vec::new(),
+            edges: HashMap::new(),
+        };
+        for _ in 0..n {
+            forest.add_vertex();
+        }
+        forest
+    }
+
+    pub fn len(&self) -> usize {
+        self.vertices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vertices.is_empty()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges.len() / 2
+    }
+
+    // Adds an isolated vertex and returns its index.
+    pub fn add_vertex(&mut self) -> usize {
+        let node = self.alloc(true);
+        self.vertices.push(node);
+        self.vertices.len() - 1
+    }
+
+    fn alloc(&mut self, is_vertex: bool) -> usize {
+        let node = TourNode {
+            size: 1,
+            vertices: is_vertex as usize,
+            is_vertex: is_vertex,
+            left: NIL,
+            right: NIL,
+            parent: NIL,
+        };
+        match self.free.pop() {
+            Some(i) => {
+                self.nodes[i] = node;
+                i
+            },
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            },
+        }
+    }
+
+    fn size(&self, i: usize) -> usize {
+        if i == NIL { 0 } else { self.nodes[i].size }
+    }
+
+    fn vertex_count(&self, i: usize) -> usize {
+        if i == NIL { 0 } else { self.nodes[i].vertices }
+    }
+
+    fn update(&mut self, i: usize) {
+        let (left, right) = (self.nodes[i].left, self.nodes[i].right);
+        self.nodes[i].size = self.size(left) + self.size(right) + 1;
+        self.nodes[i].vertices = self.vertex_count(left) + self.vertex_count(right) + self.nodes[i].is_vertex as usize;
+    }
+
+    fn set_parent(&mut self, child: usize, parent: usize) {
+        if child != NIL {
+            self.nodes[child].parent = parent;
+        }
+    }
+
+    fn rotate(&mut self, x: usize) {
+        let p = self.nodes[x].parent;
+        let g = self.nodes[p].parent;
+        if g != NIL {
+            if self.nodes[g].left == p {
+                self.nodes[g].left = x;
+            } else {
+                self.nodes[g].right = x;
+            }
+        }
+        self.nodes[x].parent = g;
+        if self.nodes[p].left == x {
+            let moved = self.nodes[x].right;
+            self.nodes[p].left = moved;
+            self.set_parent(moved, p);
+            self.nodes[x].right = p;
+        } else {
+            let moved = self.nodes[x].left;
+            self.nodes[p].right = moved;
+            self.set_parent(moved, p);
+            self.nodes[x].left = p;
+        }
+        self.nodes[p].parent = x;
+        self.update(p);
+        self.update(x);
+    }
+
+    // Splays `x` to the root of its sequence.
+    fn splay(&mut self, x: usize) {
+        while self.nodes[x].parent != NIL {
+            let p = self.nodes[x].parent;
+            let g = self.nodes[p].parent;
+            if g != NIL {
+                let zig_zig = (self.nodes[g].left == p) == (self.nodes[p].left == x);
+                self.rotate(if zig_zig { p } else { x });
+            }
+            self.rotate(x);
+        }
+    }
+
+    // The position of `x` in its sequence. Leaves `x` at the root.
+    fn position(&mut self, x: usize) -> usize {
+        self.splay(x);
+        self.size(self.nodes[x].left)
+    }
+
+    // Splits x's sequence into the part before `x` and the part from `x` on,
+    // returning their roots.
+    fn split_before(&mut self, x: usize) -> (usize, usize) {
+        self.splay(x);
+        let left = mem::replace(&mut self.nodes[x].left, NIL);
+        self.set_parent(left, NIL);
+        self.update(x);
+        (left, x)
+    }
+
+    // Splits x's sequence into the part up to `x` and the part after it.
+    fn split_after(&mut self, x: usize) -> (usize, usize) {
+        self.splay(x);
+        let right = mem::replace(&mut self.nodes[x].right, NIL);
+        self.set_parent(right, NIL);
+        self.update(x);
+        (x, right)
+    }
+
+    // Concatenates the sequences rooted at `a` and `b`, returning the root.
+    fn join(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+        let mut last = a;
+        while self.nodes[last].right != NIL {
+            last = self.nodes[last].right;
+        }
+        self.splay(last);
+        self.nodes[last].right = b;
+        self.nodes[b].parent = last;
+        self.update(last);
+        last
+    }
+
+    // Rotates v's tour so that it starts at `v`, making `v` the root of its
+    // tree. Returns the root of the sequence.
+    fn reroot(&mut self, v: usize) -> usize {
+        let x = self.vertices[v];
+        let (before, from) = self.split_before(x);
+        self.join(from, before)
+    }
+
+    pub fn connected(&mut self, u: usize, v: usize) -> bool {
+        if u == v {
+            return true;
+        }
+        let (x, y) = (self.vertices[u], self.vertices[v]);
+        // Splaying `y` moves `x` off the root exactly when they share a
+        // sequence.
+        self.splay(x);
+        self.splay(y);
+        self.nodes[x].parent != NIL
+    }
+
+    // Adds an edge between `u` and `v`. Returns false, leaving the forest
+    // unchanged, if they are already connected.
+    pub fn link(&mut self, u: usize, v: usize) -> bool {
+        if self.connected(u, v) {
+            return false;
+        }
+        let tour_u = self.reroot(u);
+        let tour_v = self.reroot(v);
+        let down = self.alloc(false);
+        let up = self.alloc(false);
+        self.edges.insert((u, v), down);
+        self.edges.insert((v, u), up);
+        let tour = self.join(tour_u, down);
+        let tour = self.join(tour, tour_v);
+        self.join(tour, up);
+        true
+    }
+
+    // Removes the edge between `u` and `v`, returning whether there was one.
+    pub fn cut(&mut self, u: usize, v: usize) -> bool {
+        let (first, second) = match (self.edges.remove(&(u, v)), self.edges.remove(&(v, u))) {
+            (Some(a), Some(b)) => if self.position(a) < self.position(b) { (a, b) } else { (b, a) },
+            _ => return false,
+        };
+        // The tour is `before, first, inside, second, after`, where `inside`
+        // is the tour of the side of the edge away from the root.
+        // Cutting both edge nodes loose leaves `inside` as a sequence of its
+        // own.
+        let (before, _) = self.split_before(first);
+        self.split_after(first);
+        self.split_before(second);
+        let (_, after) = self.split_after(second);
+        self.free.push(first);
+        self.free.push(second);
+        self.join(before, after);
+        true
+    }
+
+    // The number of vertices in v's tree.
+    pub fn tree_size(&mut self, v: usize) -> usize {
+        let x = self.vertices[v];
+        self.splay(x);
+        self.nodes[x].vertices
+    }
+
+    // The number of vertices on v's side of the edge between `u` and `v`,
+    // which is the size of v's subtree when the tree is rooted at `u`.
+    pub fn subtree_size(&mut self, u: usize, v: usize) -> Option<usize> {
+        let down = *self.edges.get(&(u, v))?;
+        let up = *self.edges.get(&(v, u))?;
+        // Edges count no vertices, so the vertices between the two
+        // traversals of the edge are those before the later one but not the
+        // earlier one.
+        let (down_at, down_before) = self.position_and_vertices(down);
+        let (up_at, up_before) = self.position_and_vertices(up);
+        if down_at < up_at {
+            Some(up_before - down_before)
+        } else {
+            Some(self.nodes[up].vertices - (down_before - up_before))
+        }
+    }
+
+    // The position of `x` and the number of vertices before it. Leaves `x`
+    // at the root.
+    fn position_and_vertices(&mut self, x: usize) -> (usize, usize) {
+        let at = self.position(x);
+        (at, self.vertex_count(self.nodes[x].left))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_and_cuts() {
+        let mut forest = EulerTourForest::new(6);
+        assert!(forest.link(0, 1));
+        assert!(forest.link(1, 2));
+        assert!(forest.link(3, 1));
+        assert!(forest.link(4, 5));
+        assert!(!forest.link(2, 3));
+        assert_eq!(forest.edge_count(), 4);
+        assert!(forest.connected(0, 3) && !forest.connected(0, 4));
+        assert_eq!((forest.tree_size(2), forest.tree_size(5)), (4, 2));
+        assert_eq!(forest.subtree_size(0, 1), Some(3));
+        assert_eq!(forest.subtree_size(1, 0), Some(1));
+        assert_eq!(forest.subtree_size(1, 2), Some(1));
+        assert_eq!(forest.subtree_size(0, 2), None);
+
+        assert!(forest.cut(2, 1));
+        assert!(!forest.cut(2, 1));
+        assert!(!forest.connected(2, 0));
+        assert!(forest.link(2, 5));
+        assert_eq!(forest.tree_size(4), 3);
+        assert_eq!(forest.subtree_size(0, 1), Some(2));
+        assert!(forest.cut(0, 1));
+        assert_eq!(forest.tree_size(0), 1);
+        assert_eq!(forest.add_vertex(), 6);
+        assert!(forest.link(6, 0));
+        assert_eq!(forest.tree_size(6), 2);
+    }
+}
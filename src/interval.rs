@@ -0,0 +1,262 @@
+use std::cmp::Ordering;
+use std::mem;
+use std::ops::{Bound, Range};
+
+use {TreeF, TreeAlgebra, TreeCoalgebra, AnnotatedTreeNode, SplayStrategy};
+use {join, splay_by};
+
+// Intervals kept in a splay tree ordered by start and then end, where every
+// node is annotated with the largest end point in its subtree. A query can
+// then skip any subtree whose intervals all end before it starts, and stop
+// as soon as it reaches intervals that start after it ends.
+//
+// Intervals are half-open, like `Range`. Annotations are recomputed with
+// `combine`, which clones the node's interval, value included, so values are
+// best kept cheap to clone: small ids, or an `Rc`.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interval<K, V> {
+    pub start: K,
+    pub end: K,
+    pub value: V,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxEnd<K>(pub Option<K>);
+
+impl<K: Ord + Copy, V> TreeAlgebra<Interval<K, V>> for MaxEnd<K> {
+    fn combine(input: TreeF<Interval<K, V>, MaxEnd<K>>) -> MaxEnd<K> {
+        match input {
+            TreeF::Empty => MaxEnd(None),
+            TreeF::Branch { val, left, right } => MaxEnd(Some(val.end).max(left.0).max(right.0)),
+        }
+    }
+}
+
+pub type IntervalNode<K, V> = AnnotatedTreeNode<Interval<K, V>, MaxEnd<K>>;
+
+fn empty<K, V>() -> IntervalNode<K, V> {
+    AnnotatedTreeNode {
+        annotation: MaxEnd(None),
+        node: TreeF::Empty,
+    }
+}
+
+#[derive(Debug)]
+pub struct SplayIntervalTree<K, V> {
+    root: IntervalNode<K, V>,
+    len: usize,
+    strategy: SplayStrategy,
+}
+
+impl<K: Ord + Copy, V: Clone> SplayIntervalTree<K, V> {
+    pub fn new() -> SplayIntervalTree<K, V> {
+        SplayIntervalTree::with_strategy(SplayStrategy::BottomUp)
+    }
+
+    pub fn with_strategy(strategy: SplayStrategy) -> SplayIntervalTree<K, V> {
+        SplayIntervalTree {
+            root: empty(),
+            len: 0,
+            strategy: strategy,
+        }
+    }
+
+    pub fn root(&self) -> &IntervalNode<K, V> {
+        &self.root
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Splays the interval `range`, or a neighbour of it if it is absent, to
+    // the root.
+    fn splay_key(&mut self, range: &Range<K>) {
+        let old_root = mem::replace(&mut self.root, empty());
+        let key = (range.start, range.end);
+        self.root = splay_by(old_root, |entry: &Interval<K, V>, _| key.cmp(&(entry.start, entry.end)), self.strategy);
+    }
+
+    // Stores `value` for `range`, returning the value it replaces if the
+    // interval was already present.
+    pub fn insert(&mut self, range: Range<K>, value: V) -> Option<V> {
+        self.splay_key(&range);
+        let entry = Interval {
+            start: range.start,
+            end: range.end,
+            value: value,
+        };
+        let (replaced, left, right) = match TreeCoalgebra::separate(mem::replace(&mut self.root, empty())) {
+            TreeF::Empty => (None, empty(), empty()),
+            TreeF::Branch { val, left, right } => {
+                match (entry.start, entry.end).cmp(&(val.start, val.end)) {
+                    Ordering::Less => (None, left, TreeAlgebra::combine(TreeF::Branch {
+                        val: val,
+                        left: empty(),
+                        right: right,
+                    })),
+                    Ordering::Equal => (Some(val.value), left, right),
+                    Ordering::Greater => (None, TreeAlgebra::combine(TreeF::Branch {
+                        val: val,
+                        left: left,
+                        right: empty(),
+                    }), right),
+                }
+            },
+        };
+        if replaced.is_none() {
+            self.len += 1;
+        }
+        self.root = TreeAlgebra::combine(TreeF::Branch {
+            val: entry,
+            left: left,
+            right: right,
+        });
+        replaced
+    }
+
+    pub fn remove(&mut self, range: &Range<K>) -> Option<V> {
+        self.splay_key(range);
+        match TreeCoalgebra::separate(mem::replace(&mut self.root, empty())) {
+            TreeF::Branch { val, left, right } => {
+                if (val.start, val.end) == (range.start, range.end) {
+                    self.root = join(left, right);
+                    self.len -= 1;
+                    Some(val.value)
+                } else {
+                    self.root = TreeAlgebra::combine(TreeF::Branch {
+                        val: val,
+                        left: left,
+                        right: right,
+                    });
+                    None
+                }
+            },
+            TreeF::Empty => None,
+        }
+    }
+
+    // The intervals containing `point`, in order. Queries only read the
+    // tree, so they leave its shape alone.
+    pub fn query_point(&self, point: K) -> Overlapping<'_, K, V> {
+        Overlapping::new(&self.root, point, Bound::Included(point))
+    }
+
+    // The intervals sharing at least one point with `range`, in order.
+    pub fn query_overlapping(&self, range: Range<K>) -> Overlapping<'_, K, V> {
+        Overlapping::new(&self.root, range.start, Bound::Excluded(range.end))
+    }
+}
+
+impl<K: Ord + Copy, V: Clone> Default for SplayIntervalTree<K, V> {
+    fn default() -> SplayIntervalTree<K, V> {
+        SplayIntervalTree::new()
+    }
+}
+
+// An in-order walk over the intervals that end after `start` and begin
+// before `end`. The stack holds nodes whose left side is being visited.
+pub struct Overlapping<'a, K: 'a, V: 'a> {
+    stack: Vec<&'a IntervalNode<K, V>>,
+    start: K,
+    end: Bound<K>,
+}
+
+impl<'a, K: Ord + Copy, V> Overlapping<'a, K, V> {
+    fn new(root: &'a IntervalNode<K, V>, start: K, end: Bound<K>) -> Overlapping<'a, K, V> {
+        let mut iter = Overlapping {
+            stack: Vec::new(),
+            start: start,
+            end: end,
+        };
+        // An empty range has no points to share.
+        if iter.starts_before_end(start) {
+            iter.descend(root);
+        }
+        iter
+    }
+
+    fn ends_after_start(&self, end: K) -> bool {
+        end > self.start
+    }
+
+    fn starts_before_end(&self, start: K) -> bool {
+        match self.end {
+            Bound::Included(end) => start <= end,
+            Bound::Excluded(end) => start < end,
+            Bound::Unbounded => true,
+        }
+    }
+
+    // Follows left children for as long as some interval below still ends
+    // after the query starts.
+    fn descend(&mut self, node: &'a IntervalNode<K, V>) {
+        let mut node = node;
+        while let TreeF::Branch { ref left, .. } = node.node {
+            match node.annotation.0 {
+                Some(end) if self.ends_after_start(end) => {},
+                _ => break,
+            }
+            self.stack.push(node);
+            node = left;
+        }
+    }
+}
+
+impl<'a, K: Ord + Copy, V> Iterator for Overlapping<'a, K, V> {
+    type Item = &'a Interval<K, V>;
+
+    fn next(&mut self) -> Option<&'a Interval<K, V>> {
+        while let Some(node) = self.stack.pop() {
+            if let TreeF::Branch { ref val, ref right, .. } = node.node {
+                // Everything from here on starts at least as late.
+                if !self.starts_before_end(val.start) {
+                    self.stack.clear();
+                    return None;
+                }
+                self.descend(right);
+                if self.ends_after_start(val.end) {
+                    return Some(val);
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans<'a, I: Iterator<Item = &'a Interval<i32, char>>>(iter: I) -> Vec<(i32, i32, char)> {
+        iter.map(|interval| (interval.start, interval.end, interval.value)).collect()
+    }
+
+    #[test]
+    fn stabbing_and_overlap_queries() {
+        for &strategy in &[SplayStrategy::BottomUp, SplayStrategy::TopDown] {
+            let mut tree = SplayIntervalTree::with_strategy(strategy);
+            for (i, &(start, end)) in [(5, 10), (1, 3), (8, 20), (12, 15), (0, 30), (3, 5)].iter().enumerate() {
+                assert_eq!(tree.insert(start..end, (b'a' + i as u8) as char), None);
+            }
+            assert_eq!(tree.insert(1..3, 'B'), Some('b'));
+            assert_eq!(tree.len(), 6);
+            assert!(tree.root().validate().is_ok());
+            assert_eq!(spans(tree.query_point(3)), vec![(0, 30, 'e'), (3, 5, 'f')]);
+            assert_eq!(spans(tree.query_point(30)), vec![]);
+            assert_eq!(spans(tree.query_overlapping(9..12)), vec![(0, 30, 'e'), (5, 10, 'a'), (8, 20, 'c')]);
+            assert_eq!(spans(tree.query_overlapping(4..4)), vec![]);
+            assert_eq!(tree.remove(&(0..30)), Some('e'));
+            assert_eq!(tree.remove(&(0..30)), None);
+            assert_eq!(tree.remove(&(4..5)), None);
+            assert!(tree.root().validate().is_ok());
+            assert_eq!(spans(tree.query_overlapping(0..100)).len(), 5);
+            assert_eq!(spans(tree.query_point(13)), vec![(8, 20, 'c'), (12, 15, 'd')]);
+        }
+    }
+}
@@ -0,0 +1,2053 @@
+#![allow(clippy::redundant_field_names)]
+
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+use stats::Stats;
+
+pub mod arena;
+pub mod command;
+pub mod dot;
+pub mod euler_tour;
+pub mod interval;
+pub mod link_cut;
+pub mod multiset;
+pub mod persistent;
+pub mod pretty;
+pub mod rope;
+pub mod sequence;
+#[cfg(feature = "serde")]
+pub mod serialization;
+pub mod snapshot;
+pub mod stats;
+pub mod validate;
+pub mod workload;
+
+#[derive(Debug)]
+pub enum TreeF<A, B> {
+    Empty,
+    Branch { val: A, left: B, right: B },
+}
+
+pub trait TreeAlgebra<A> where Self: Sized {
+    fn combine(input: TreeF<A, Self>) -> Self;
+}
+
+pub trait TreeCoalgebra<A> where Self: Sized {
+    fn separate(input: Self) -> TreeF<A, Self>;
+    fn is_branch(input: &Self) -> bool;
+}
+
+// Trees that track the number of elements below each node. This is what lets
+// us treat a tree as an implicit sequence and navigate it by index.
+pub trait SubtreeSize {
+    fn subtree_size(input: &Self) -> usize;
+}
+
+#[derive(Debug)]
+pub struct TreeNode<A>(pub TreeF<A, Box<TreeNode<A>>>);
+
+impl<A> TreeAlgebra<A> for TreeNode<A> {
+    fn combine(input: TreeF<A, TreeNode<A>>) -> TreeNode<A> {
+        match input {
+            TreeF::Empty => {
+                TreeNode(TreeF::Empty)
+            },
+            TreeF::Branch { val, left, right } => {
+                stats::record(|stats| stats.allocations += 2);
+                TreeNode(TreeF::Branch {
+                    val: val,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                })
+            },
+        }
+    }
+}
+
+impl<A> TreeCoalgebra<A> for TreeNode<A> {
+    fn separate(input: TreeNode<A>) -> TreeF<A, TreeNode<A>> {
+        match input {
+            TreeNode(TreeF::Empty) => {
+                TreeF::Empty
+            },
+            TreeNode(TreeF::Branch { val, left, right }) => {
+                TreeF::Branch {
+                    val: val,
+                    left: *left,
+                    right: *right,
+                }
+            },
+        }
+    }
+    fn is_branch(input: &TreeNode<A>) -> bool {
+        match *input {
+            TreeNode(TreeF::Empty) => false,
+            TreeNode(TreeF::Branch {..}) => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AnnotatedTreeNode<A, B> {
+    pub annotation: B,
+    pub node: TreeF<A, Box<AnnotatedTreeNode<A, B>>>,
+}
+
+impl<A: Clone, B: TreeAlgebra<A> + Copy> TreeAlgebra<A> for AnnotatedTreeNode<A, B> {
+    fn combine(input: TreeF<A, AnnotatedTreeNode<A, B>>) -> AnnotatedTreeNode<A, B> {
+        match input {
+            TreeF::Empty => {
+                AnnotatedTreeNode {
+                    annotation: TreeAlgebra::combine(TreeF::Empty),
+                    node: TreeF::Empty,
+                }
+            },
+            TreeF::Branch { val, left, right } => {
+                let new_ann = TreeAlgebra::combine(TreeF::Branch {
+                    val: val.clone(),
+                    left: left.annotation,
+                    right: right.annotation
+                });
+                stats::record(|stats| stats.allocations += 2);
+                AnnotatedTreeNode {
+                    annotation: new_ann,
+                    node: TreeF::Branch {
+                        val: val,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    }
+                }
+            },
+        }
+    }
+}
+
+impl<A, B> TreeCoalgebra<A> for AnnotatedTreeNode<A, B> {
+    fn separate(input: AnnotatedTreeNode<A, B>) -> TreeF<A, AnnotatedTreeNode<A, B>> {
+        match input.node {
+            TreeF::Empty => {
+                TreeF::Empty
+            },
+            TreeF::Branch { val, left, right } => {
+                TreeF::Branch {
+                    val: val,
+                    left: *left,
+                    right: *right,
+                }
+            }
+        }
+    }
+    fn is_branch(input: &AnnotatedTreeNode<A, B>) -> bool {
+        match input.node {
+            TreeF::Empty => false,
+            TreeF::Branch {..} => true,
+        }
+    }
+}
+
+// In order to simplify many of the tree operations, we define a zipper type,
+// which intuitively represents a location on the tree. To be precise, a zipper
+// consists of the following parts:
+// 1) A sequence of steps down the tree. Each step contains:
+//    a) a direction (left or right)
+//    b) the value in the parent node
+//    c) the sibling subtree
+// 2) The subtree below our location.
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+pub(crate) struct TreeZipperStep<A, B> {
+    pub(crate) direction: Direction,
+    pub(crate) parent_val: A,
+    pub(crate) sibling: B,
+}
+
+#[derive(Debug)]
+pub(crate) struct TreeZipper<A, B> {
+    pub(crate) path: Vec<TreeZipperStep<A, B>>,
+    pub(crate) here: B,
+}
+
+pub(crate) fn find<A : Ord, B : TreeCoalgebra<A> + TreeAlgebra<A>>(root: B, v: &A) -> TreeZipper<A, B> {
+    find_by(root, |val, _| v.cmp(val))
+}
+
+// Walks down as `nav` directs, with the same meaning as in `splay_top_down`
+// below, stopping at the node it reports Equal for or at an empty subtree.
+pub(crate) fn find_by<A, B, F>(root: B, mut nav: F) -> TreeZipper<A, B>
+    where B: TreeCoalgebra<A> + TreeAlgebra<A>, F: FnMut(&A, &B) -> Ordering {
+    let mut path = Vec::new();
+    let mut node = root;
+    loop {
+        match TreeCoalgebra::separate(node) {
+            TreeF::Empty => {
+                node = TreeAlgebra::combine(TreeF::Empty);
+                break;
+            },
+            TreeF::Branch { val, left, right } => {
+                match nav(&val, &left) {
+                    Ordering::Less => {
+                        path.push(TreeZipperStep {
+                            direction: Direction::Left,
+                            parent_val: val,
+                            sibling: right,
+                        });
+                        node = left;
+                    },
+                    Ordering::Equal => {
+                        node = TreeAlgebra::combine(TreeF::Branch { val: val, left: left, right: right });
+                        break;
+                    },
+                    Ordering::Greater => {
+                        path.push(TreeZipperStep {
+                            direction: Direction::Right,
+                            parent_val: val,
+                            sibling: left,
+                        });
+                        node = right;
+                    },
+                };
+            },
+        };
+    }
+    stats::record_path(path.len());
+    TreeZipper {
+        path: path,
+        here: node,
+    }
+}
+
+pub(crate) fn zip_tree<A, B : TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> B {
+    let mut path = zipper.path;
+    let mut node = zipper.here;
+    while let Some(TreeZipperStep {direction, parent_val, sibling}) = path.pop() {
+        match direction {
+            Direction::Left => {
+                node = TreeAlgebra::combine(TreeF::Branch {
+                    val: parent_val,
+                    left: node,
+                    right: sibling,
+                });
+            },
+            Direction::Right => {
+                node = TreeAlgebra::combine(TreeF::Branch {
+                    val: parent_val,
+                    left: sibling,
+                    right: node,
+                });
+            },
+        }
+    }
+    node
+}
+
+pub(crate) fn root_zipper<A, B>(root: B) -> TreeZipper<A, B> {
+    let path = Vec::new();
+    TreeZipper {
+        path: path,
+        here: root,
+    }
+}
+
+pub(crate) fn parent_zipper<A, B: TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
+    let mut path = zipper.path;
+    match path.pop() {
+        None => {
+            TreeZipper {
+                path: path,
+                here: zipper.here,
+            }
+        },
+        Some(TreeZipperStep {direction, parent_val, sibling}) => {
+            match direction {
+                Direction::Left => {
+                    TreeZipper {
+                        path: path,
+                        here: TreeAlgebra::combine(TreeF::Branch {
+                            val: parent_val,
+                            left: zipper.here,
+                            right: sibling,
+                        }),
+                    }
+                },
+                Direction::Right => {
+                    TreeZipper {
+                        path: path,
+                        here: TreeAlgebra::combine(TreeF::Branch {
+                            val: parent_val,
+                            left: sibling,
+                            right: zipper.here,
+                        }),
+                    }
+                },
+            }
+        },
+    }
+}
+
+pub(crate) fn left_zipper<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
+    let mut path = zipper.path;
+    let mut node = zipper.here;
+    match TreeCoalgebra::separate(node) {
+        TreeF::Empty => {
+            node = TreeAlgebra::combine(TreeF::Empty);
+        },
+        TreeF::Branch{val, left, right} => {
+            path.push(TreeZipperStep {
+                direction: Direction::Left,
+                parent_val: val,
+                sibling: right,
+            });
+            node = left;
+        },
+    }
+    TreeZipper {
+        path: path,
+        here: node,
+    }
+}
+
+pub(crate) fn right_zipper<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
+    let mut path = zipper.path;
+    let mut node = zipper.here;
+    match TreeCoalgebra::separate(node) {
+        TreeF::Empty => {
+            node = TreeAlgebra::combine(TreeF::Empty);
+        },
+        TreeF::Branch{val, left, right} => {
+            path.push(TreeZipperStep {
+                direction: Direction::Right,
+                parent_val: val,
+                sibling: left,
+            });
+            node = right;
+        },
+    }
+    TreeZipper {
+        path: path,
+        here: node,
+    }
+}
+
+pub(crate) fn rotate_zipper<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
+    let mut path = zipper.path;
+    let mut node = zipper.here;
+    match TreeCoalgebra::separate(node) {
+        TreeF::Empty => {
+            node = TreeAlgebra::combine(TreeF::Empty);
+        },
+        TreeF::Branch {val, left, right} => {
+            if let Some(TreeZipperStep {direction, parent_val, sibling}) = path.pop() {
+                match direction {
+                    Direction::Left => {
+                        node = TreeAlgebra::combine(TreeF::Branch {
+                            val: val,
+                            left: left,
+                            right: TreeAlgebra::combine(TreeF::Branch {
+                                val: parent_val,
+                                left: right,
+                                right: sibling,
+                            }),
+                        });
+                    },
+                    Direction::Right => {
+                        node = TreeAlgebra::combine(TreeF::Branch {
+                            val: val,
+                            left: TreeAlgebra::combine(TreeF::Branch {
+                                val: parent_val,
+                                left: sibling,
+                                right: left,
+                            }),
+                            right: right,
+                        });
+                    },
+                }
+            } else {
+                node = TreeAlgebra::combine(TreeF::Branch{
+                    val: val,
+                    left: left,
+                    right: right,
+                });
+            }
+        },
+    }
+    TreeZipper {
+        path: path,
+        here: node,
+    }
+}
+
+pub(crate) fn splay<A, B: TreeAlgebra<A> + TreeCoalgebra<A>>(mut zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
+    if zipper.path.is_empty() {
+        return zipper
+    }
+    if !TreeCoalgebra::is_branch(&zipper.here) {
+        zipper = parent_zipper(zipper)
+    }
+    while !zipper.path.is_empty() {
+        zipper = splay_step(zipper);
+    }
+    zipper
+}
+
+fn record_step<A, B>(path: &[TreeZipperStep<A, B>]) {
+    let n = path.len();
+    match n {
+        0 => {},
+        1 => stats::record(|stats| stats.zigs += 1),
+        _ if path[n - 1].direction == path[n - 2].direction => stats::record(|stats| stats.zig_zigs += 1),
+        _ => stats::record(|stats| stats.zig_zags += 1),
+    }
+}
+
+pub(crate) fn splay_step<A, B: TreeAlgebra<A> + TreeCoalgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
+    if TreeCoalgebra::is_branch(&zipper.here) {
+        record_step(&zipper.path);
+    }
+    let mut path = zipper.path;
+    match TreeCoalgebra::separate(zipper.here) {
+        TreeF::Empty => TreeZipper{path: path, here: TreeAlgebra::combine(TreeF::Empty)},
+        TreeF::Branch {val, left, right} => {
+            match path.pop() {
+                None => {
+                    TreeZipper{path: path, here: TreeAlgebra::combine(TreeF::Branch{val: val, left: left, right: right})}
+                },
+                Some(TreeZipperStep {direction, parent_val, sibling}) => {
+                    match path.pop() {
+                        None => {
+                            match direction {
+                                Direction::Left => {
+                                    TreeZipper {
+                                        path: path,
+                                        here: TreeAlgebra::combine(TreeF::Branch {
+                                            val: val,
+                                            left: left,
+                                            right: TreeAlgebra::combine(TreeF::Branch {
+                                                val: parent_val,
+                                                left: right,
+                                                right: sibling,
+                                            }),
+                                        }),
+                                    }
+                                },
+                                Direction::Right => {
+                                    TreeZipper {
+                                        path: path,
+                                        here: TreeAlgebra::combine(TreeF::Branch {
+                                            val: val,
+                                            left: TreeAlgebra::combine(TreeF::Branch {
+                                                val: parent_val,
+                                                left: sibling,
+                                                right: left,
+                                            }),
+                                            right: right,
+                                        }),
+                                    }
+                                },
+                            }
+                        },
+                        Some(TreeZipperStep {direction: parent_dir, parent_val: grandparent_val, sibling: uncle}) => {
+                            match (direction, parent_dir) {
+                                (Direction::Left, Direction::Left) => {
+                                    TreeZipper {
+                                        path: path,
+                                        here: TreeAlgebra::combine(TreeF::Branch {
+                                            val: val,
+                                            left: left,
+                                            right: TreeAlgebra::combine(TreeF::Branch {
+                                                val: parent_val,
+                                                left: right,
+                                                right: TreeAlgebra::combine(TreeF::Branch {
+                                                    val: grandparent_val,
+                                                    left: sibling,
+                                                    right: uncle,
+                                                }),
+                                            }),
+                                        }),
+                                    }
+                                },
+                                (Direction::Left, Direction::Right) => {
+                                    TreeZipper {
+                                        path: path,
+                                        here: TreeAlgebra::combine(TreeF::Branch {
+                                            val: val,
+                                            left: TreeAlgebra::combine(TreeF::Branch {
+                                                val: grandparent_val,
+                                                left: uncle,
+                                                right: left,
+                                            }),
+                                            right: TreeAlgebra::combine(TreeF::Branch {
+                                                val: parent_val,
+                                                left: right,
+                                                right: sibling,
+                                            }),
+                                        }),
+                                    }
+                                },
+                                (Direction::Right, Direction::Left) => {
+                                    TreeZipper {
+                                        path: path,
+                                        here: TreeAlgebra::combine(TreeF::Branch {
+                                            val: val,
+                                            left: TreeAlgebra::combine(TreeF::Branch {
+                                                val: parent_val,
+                                                left: sibling,
+                                                right: left,
+                                            }),
+                                            right: TreeAlgebra::combine(TreeF::Branch {
+                                                val: grandparent_val,
+                                                left: right,
+                                                right: uncle,
+                                            }),
+                                        }),
+                                    }
+                                },
+                                (Direction::Right, Direction::Right) => {
+                                    TreeZipper {
+                                        path: path,
+                                        here: TreeAlgebra::combine(TreeF::Branch {
+                                            val: val,
+                                            left: TreeAlgebra::combine(TreeF::Branch {
+                                                val: parent_val,
+                                                left: TreeAlgebra::combine(TreeF::Branch {
+                                                    val: grandparent_val,
+                                                    left: uncle,
+                                                    right: sibling,
+                                                }),
+                                                right: left,
+                                            }),
+                                            right: right,
+                                        }),
+                                    }
+                                },
+                            }
+                        },
+                    }
+                },
+            }
+        },
+    }
+}
+
+// The zipper based splay above walks down recording a path and then walks back
+// up rotating. Top-down splaying (Sleator and Tarjan's left/right tree
+// assembly) instead restructures on the way down, so no path is needed. The
+// nodes found to lie before the target are collected on `smaller`, chained
+// through their right children with the most recently linked node on top, and
+// `larger` is the mirror image. Once the target is reached both chains are
+// unwound onto its subtrees, which rebuilds them in the right order and
+// recomputes any annotations.
+//
+// `nav` is called at most once per node and reports whether the target lies to
+// the left of (Less), at (Equal) or to the right of (Greater) the node, given
+// its value and left subtree.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplayStrategy {
+    BottomUp,
+    TopDown,
+}
+
+pub(crate) fn splay_top_down<A, B, F>(root: B, mut nav: F) -> B
+    where B: TreeAlgebra<A> + TreeCoalgebra<A>, F: FnMut(&A, &B) -> Ordering {
+    let (mut val, mut left, mut right) = match TreeCoalgebra::separate(root) {
+        TreeF::Empty => return TreeAlgebra::combine(TreeF::Empty),
+        TreeF::Branch { val, left, right } => (val, left, right),
+    };
+    let mut smaller: B = TreeAlgebra::combine(TreeF::Empty);
+    let mut larger: B = TreeAlgebra::combine(TreeF::Empty);
+    let mut pending = None;
+    let mut depth = 0;
+    loop {
+        let ord = match pending.take() {
+            Some(ord) => ord,
+            None => nav(&val, &left),
+        };
+        match ord {
+            Ordering::Less => {
+                match TreeCoalgebra::separate(left) {
+                    TreeF::Empty => {
+                        left = TreeAlgebra::combine(TreeF::Empty);
+                        break;
+                    },
+                    TreeF::Branch { val: child_val, left: child_left, right: child_right } => {
+                        let child_ord = nav(&child_val, &child_left);
+                        if child_ord == Ordering::Less && TreeCoalgebra::is_branch(&child_left) {
+                            stats::record(|stats| stats.zig_zigs += 1);
+                            depth += 2;
+                            larger = TreeAlgebra::combine(TreeF::Branch {
+                                val: child_val,
+                                left: larger,
+                                right: TreeAlgebra::combine(TreeF::Branch {
+                                    val: val,
+                                    left: child_right,
+                                    right: right,
+                                }),
+                            });
+                            match TreeCoalgebra::separate(child_left) {
+                                TreeF::Empty => unreachable!(),
+                                TreeF::Branch { val: v, left: l, right: r } => {
+                                    val = v;
+                                    left = l;
+                                    right = r;
+                                },
+                            }
+                        } else {
+                            stats::record(|stats| stats.zigs += 1);
+                            depth += 1;
+                            larger = TreeAlgebra::combine(TreeF::Branch {
+                                val: val,
+                                left: larger,
+                                right: right,
+                            });
+                            val = child_val;
+                            left = child_left;
+                            right = child_right;
+                            pending = Some(child_ord);
+                        }
+                    },
+                }
+            },
+            Ordering::Equal => break,
+            Ordering::Greater => {
+                match TreeCoalgebra::separate(right) {
+                    TreeF::Empty => {
+                        right = TreeAlgebra::combine(TreeF::Empty);
+                        break;
+                    },
+                    TreeF::Branch { val: child_val, left: child_left, right: child_right } => {
+                        let child_ord = nav(&child_val, &child_left);
+                        if child_ord == Ordering::Greater && TreeCoalgebra::is_branch(&child_right) {
+                            stats::record(|stats| stats.zig_zigs += 1);
+                            depth += 2;
+                            smaller = TreeAlgebra::combine(TreeF::Branch {
+                                val: child_val,
+                                left: TreeAlgebra::combine(TreeF::Branch {
+                                    val: val,
+                                    left: left,
+                                    right: child_left,
+                                }),
+                                right: smaller,
+                            });
+                            match TreeCoalgebra::separate(child_right) {
+                                TreeF::Empty => unreachable!(),
+                                TreeF::Branch { val: v, left: l, right: r } => {
+                                    val = v;
+                                    left = l;
+                                    right = r;
+                                },
+                            }
+                        } else {
+                            stats::record(|stats| stats.zigs += 1);
+                            depth += 1;
+                            smaller = TreeAlgebra::combine(TreeF::Branch {
+                                val: val,
+                                left: left,
+                                right: smaller,
+                            });
+                            val = child_val;
+                            left = child_left;
+                            right = child_right;
+                            pending = Some(child_ord);
+                        }
+                    },
+                }
+            },
+        }
+    }
+    stats::record_path(depth);
+    while let TreeF::Branch { val: v, left: l, right: rest } = TreeCoalgebra::separate(smaller) {
+        left = TreeAlgebra::combine(TreeF::Branch { val: v, left: l, right: left });
+        smaller = rest;
+    }
+    while let TreeF::Branch { val: v, left: rest, right: r } = TreeCoalgebra::separate(larger) {
+        right = TreeAlgebra::combine(TreeF::Branch { val: v, left: right, right: r });
+        larger = rest;
+    }
+    TreeAlgebra::combine(TreeF::Branch {
+        val: val,
+        left: left,
+        right: right,
+    })
+}
+
+pub(crate) fn splay_key<A: Ord, B: TreeAlgebra<A> + TreeCoalgebra<A>>(root: B, v: &A, strategy: SplayStrategy) -> B {
+    splay_by(root, |val, _| v.cmp(val), strategy)
+}
+
+// Splays the node `nav` leads to, or the last node on the way if it runs off
+// the tree.
+pub(crate) fn splay_by<A, B, F>(root: B, nav: F, strategy: SplayStrategy) -> B
+    where B: TreeAlgebra<A> + TreeCoalgebra<A>, F: FnMut(&A, &B) -> Ordering {
+    match strategy {
+        SplayStrategy::BottomUp => zip_tree(splay(find_by(root, nav))),
+        SplayStrategy::TopDown => splay_top_down(root, nav),
+    }
+}
+
+pub(crate) fn splay_index<A, B: TreeAlgebra<A> + TreeCoalgebra<A> + SubtreeSize>(root: B, index: usize, strategy: SplayStrategy) -> B {
+    match strategy {
+        SplayStrategy::BottomUp => zip_tree(splay(find_index(root, index))),
+        SplayStrategy::TopDown => {
+            let mut remaining = index;
+            splay_top_down(root, |_, left| {
+                let left_size = get_size(left);
+                let ord = remaining.cmp(&left_size);
+                if ord == Ordering::Greater {
+                    remaining = remaining - left_size - 1;
+                }
+                ord
+            })
+        },
+    }
+}
+
+// How much a tree restructures itself after an access. Full splaying is the
+// default; the other policies trade some of its amortized guarantees for less
+// restructuring on workloads dominated by reads.
+//
+// SemiSplay only rotates the parent in the zig-zig case, roughly halving the
+// depth of the access path instead of moving the node all the way up.
+// DepthThreshold(d) splays only when the access path is longer than d.
+// NoSplayOnRead leaves the tree untouched on lookups and splays on writes.
+//
+// Semi-splaying and the depth threshold need the access path, so they always
+// use the bottom-up zipper regardless of the tree's strategy. Range operations
+// that need their endpoints at the root always splay fully.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplayPolicy {
+    Full,
+    SemiSplay,
+    DepthThreshold(usize),
+    NoSplayOnRead,
+}
+
+impl SplayPolicy {
+    // Whether an access can be handled by the tree's splay strategy directly,
+    // rather than by finding a zipper and adjusting it afterwards.
+    pub(crate) fn splays_fully(self, read: bool) -> bool {
+        match self {
+            SplayPolicy::Full => true,
+            SplayPolicy::NoSplayOnRead => !read,
+            SplayPolicy::SemiSplay | SplayPolicy::DepthThreshold(_) => false,
+        }
+    }
+}
+
+pub(crate) fn semi_splay<A, B: TreeAlgebra<A> + TreeCoalgebra<A>>(mut zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
+    if zipper.path.is_empty() {
+        return zipper
+    }
+    if !TreeCoalgebra::is_branch(&zipper.here) {
+        zipper = parent_zipper(zipper)
+    }
+    while !zipper.path.is_empty() {
+        let n = zipper.path.len();
+        if n >= 2 && zipper.path[n - 1].direction == zipper.path[n - 2].direction {
+            stats::record(|stats| stats.zigs += 1);
+            zipper = rotate_zipper(parent_zipper(zipper));
+        } else {
+            zipper = splay_step(zipper);
+        }
+    }
+    zipper
+}
+
+// Restores a tree from a zipper, restructuring it as `policy` asks for.
+pub(crate) fn adjust<A, B: TreeAlgebra<A> + TreeCoalgebra<A>>(zipper: TreeZipper<A, B>, policy: SplayPolicy, read: bool) -> B {
+    match policy {
+        SplayPolicy::Full => zip_tree(splay(zipper)),
+        SplayPolicy::SemiSplay => zip_tree(semi_splay(zipper)),
+        SplayPolicy::DepthThreshold(depth) => {
+            if zipper.path.len() > depth {
+                zip_tree(splay(zipper))
+            } else {
+                zip_tree(zipper)
+            }
+        },
+        SplayPolicy::NoSplayOnRead => {
+            if read {
+                zip_tree(zipper)
+            } else {
+                zip_tree(splay(zipper))
+            }
+        },
+    }
+}
+
+#[derive(Debug)]
+pub struct SplayTree<A> {
+    root: TreeNode<A>,
+    strategy: SplayStrategy,
+    policy: SplayPolicy,
+    stats: Option<Stats>,
+}
+
+impl<A: Ord> SplayTree<A> {
+    pub fn with_strategy(strategy: SplayStrategy) -> SplayTree<A> {
+        SplayTree {
+            root: TreeNode(TreeF::Empty),
+            strategy: strategy,
+            policy: SplayPolicy::Full,
+            stats: None,
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: SplayPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn root(&self) -> &TreeNode<A> {
+        &self.root
+    }
+
+    // Starts counting rotations, path lengths and allocations from zero.
+    pub fn enable_stats(&mut self) {
+        self.stats = Some(Stats::new());
+    }
+
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    fn observed<R, F: FnOnce(&mut SplayTree<A>) -> R>(&mut self, f: F) -> R {
+        let (result, stats) = stats::collect(self.stats.clone(), || f(&mut *self));
+        self.stats = stats;
+        result
+    }
+}
+
+pub trait Splay<A> {
+    fn new() -> Self;
+    fn insert(&mut self, v: A);
+    fn contains(&mut self, v: A) -> bool;
+    fn splay_to_root(&mut self, v: A);
+}
+
+impl<A: Ord> Splay<A> for SplayTree<A> {
+    fn new() -> SplayTree<A> {
+        SplayTree::with_strategy(SplayStrategy::BottomUp)
+    }
+
+    fn insert(&mut self, v: A) {
+        self.observed(|tree| {
+            let old_root = mem::replace(&mut tree.root, TreeNode(TreeF::Empty));
+            if tree.strategy == SplayStrategy::TopDown && tree.policy.splays_fully(false) {
+                // Splay the neighbour of `v` to the root and split around it.
+                tree.root = match TreeCoalgebra::separate(splay_key(old_root, &v, tree.strategy)) {
+                    TreeF::Empty => TreeAlgebra::combine(TreeF::Branch {
+                        val: v,
+                        left: TreeNode(TreeF::Empty),
+                        right: TreeNode(TreeF::Empty),
+                    }),
+                    TreeF::Branch { val, left, right } => {
+                        match v.cmp(&val) {
+                            Ordering::Less => TreeAlgebra::combine(TreeF::Branch {
+                                val: v,
+                                left: left,
+                                right: TreeAlgebra::combine(TreeF::Branch {
+                                    val: val,
+                                    left: TreeNode(TreeF::Empty),
+                                    right: right,
+                                }),
+                            }),
+                            Ordering::Equal => TreeAlgebra::combine(TreeF::Branch {
+                                val: val,
+                                left: left,
+                                right: right,
+                            }),
+                            Ordering::Greater => TreeAlgebra::combine(TreeF::Branch {
+                                val: v,
+                                left: TreeAlgebra::combine(TreeF::Branch {
+                                    val: val,
+                                    left: left,
+                                    right: TreeNode(TreeF::Empty),
+                                }),
+                                right: right,
+                            }),
+                        }
+                    },
+                };
+                return;
+            }
+            let mut ins_loc = find(old_root, &v);
+            if let TreeNode(TreeF::Empty) = ins_loc.here {
+                stats::record(|stats| stats.allocations += 2);
+                ins_loc.here = TreeNode(TreeF::Branch {
+                    val: v,
+                    left: Box::new(TreeNode(TreeF::Empty)),
+                    right: Box::new(TreeNode(TreeF::Empty)),
+                });
+            }
+            tree.root = adjust(ins_loc, tree.policy, false);
+        })
+    }
+
+    fn contains(&mut self, v: A) -> bool {
+        self.observed(|tree| {
+            let old_root = mem::replace(&mut tree.root, TreeNode(TreeF::Empty));
+            if !tree.policy.splays_fully(true) {
+                let find_loc = find(old_root, &v);
+                let result = TreeCoalgebra::is_branch(&find_loc.here);
+                tree.root = adjust(find_loc, tree.policy, true);
+                return result;
+            }
+            tree.root = splay_key(old_root, &v, tree.strategy);
+            match tree.root {
+                TreeNode(TreeF::Empty) => false,
+                TreeNode(TreeF::Branch { ref val, .. }) => *val == v,
+            }
+        })
+    }
+
+    fn splay_to_root(&mut self, v: A) {
+        self.observed(|tree| {
+            let old_root = mem::replace(&mut tree.root, TreeNode(TreeF::Empty));
+            tree.root = splay_key(old_root, &v, tree.strategy);
+        })
+    }
+}
+
+// An update to every bit of a subtree that has not been pushed down to its
+// children yet. Flips and fills compose, so one pending operation per node is
+// enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    Keep,
+    Flip,
+    Fill(bool),
+}
+
+impl BitOp {
+    pub fn apply(self, bit: bool) -> bool {
+        match self {
+            BitOp::Keep => bit,
+            BitOp::Flip => !bit,
+            BitOp::Fill(b) => b,
+        }
+    }
+
+    // The operation equivalent to applying self and then next.
+    pub fn then(self, next: BitOp) -> BitOp {
+        match (self, next) {
+            (op, BitOp::Keep) => op,
+            (BitOp::Keep, op) => op,
+            (_, BitOp::Fill(b)) => BitOp::Fill(b),
+            (BitOp::Flip, BitOp::Flip) => BitOp::Keep,
+            (BitOp::Fill(b), BitOp::Flip) => BitOp::Fill(!b),
+        }
+    }
+
+    // A compact code for the operation: 0 for none, 1 for a flip and 2 or 3
+    // for filling with zeros or ones.
+    pub fn code(self) -> u8 {
+        match self {
+            BitOp::Keep => 0,
+            BitOp::Flip => 1,
+            BitOp::Fill(b) => 2 + b as u8,
+        }
+    }
+
+    pub fn from_code(code: u8) -> Option<BitOp> {
+        match code {
+            0 => Some(BitOp::Keep),
+            1 => Some(BitOp::Flip),
+            2 | 3 => Some(BitOp::Fill(code == 3)),
+            _ => None,
+        }
+    }
+
+    // The number of set bits after applying the operation to `size` bits of
+    // which `ones` were set.
+    pub fn ones(self, ones: usize, size: usize) -> usize {
+        match self {
+            BitOp::Keep => ones,
+            BitOp::Flip => size - ones,
+            BitOp::Fill(b) => if b { size } else { 0 },
+        }
+    }
+}
+
+// Subtree sizes and counts of set bits as stored in the nodes of a sequence,
+// in 48 bits. That is more nodes than fit in memory, and unlike a pair of
+// usizes it keeps a bit range node at 32 bytes on 64-bit targets.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Count([u16; 3]);
+
+impl Count {
+    pub const MAX: u64 = (1 << 48) - 1;
+
+    pub fn new(n: usize) -> Count {
+        let n = n as u64;
+        assert!(n <= Count::MAX, "count {} does not fit in 48 bits", n);
+        Count([n as u16, (n >> 16) as u16, (n >> 32) as u16])
+    }
+
+    pub fn get(self) -> usize {
+        (self.0[0] as u64 | (self.0[1] as u64) << 16 | (self.0[2] as u64) << 32) as usize
+    }
+}
+
+// The low word is stored first, so the derived ordering would compare it
+// first; compare the numbers instead.
+impl Ord for Count {
+    fn cmp(&self, other: &Count) -> Ordering {
+        self.get().cmp(&other.get())
+    }
+}
+
+impl PartialOrd for Count {
+    fn partial_cmp(&self, other: &Count) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Count {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+impl fmt::Display for Count {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.get())
+    }
+}
+
+// A node's own bit and counts always reflect every operation applied to it;
+// `reversed` and `pending` describe work still owed to its children.
+#[derive(Debug)]
+pub enum BitRangeNode {
+    Empty,
+    Branch {
+        here: bool,
+        size: Count,
+        ones: Count,
+        reversed: bool,
+        pending: BitOp,
+        left: Box<BitRangeNode>,
+        right: Box<BitRangeNode>,
+    }
+}
+
+impl BitRangeNode {
+    pub fn updated(input: BitRangeNode, op: BitOp) -> BitRangeNode {
+        match input {
+            BitRangeNode::Empty => {
+                BitRangeNode::Empty
+            },
+            BitRangeNode::Branch { here, size, ones, reversed, pending, left, right } => {
+                BitRangeNode::Branch {
+                    here: op.apply(here),
+                    size: size,
+                    ones: Count::new(op.ones(ones.get(), size.get())),
+                    reversed: reversed,
+                    pending: pending.then(op),
+                    left: left,
+                    right: right,
+                }
+            },
+        }
+    }
+}
+
+fn get_ones(n: &BitRangeNode) -> usize {
+    match *n {
+        BitRangeNode::Empty => 0,
+        BitRangeNode::Branch { ones, .. } => ones.get(),
+    }
+}
+
+pub trait Reversible {
+    fn reversed(input: Self) -> Self;
+}
+
+impl Reversible for BitRangeNode {
+    fn reversed(input: BitRangeNode) -> BitRangeNode {
+        match input {
+            BitRangeNode::Empty => {
+                BitRangeNode::Empty
+            },
+            BitRangeNode::Branch { here, size, ones, reversed, pending, left, right } => {
+                BitRangeNode::Branch {
+                    here: here,
+                    size: size,
+                    ones: ones,
+                    reversed: !reversed,
+                    pending: pending,
+                    left: left,
+                    right: right,
+                }
+            },
+        }
+    }
+}
+
+fn get_size<B: SubtreeSize>(n: &B) -> usize {
+    SubtreeSize::subtree_size(n)
+}
+
+impl SubtreeSize for BitRangeNode {
+    fn subtree_size(input: &BitRangeNode) -> usize {
+        match *input {
+            BitRangeNode::Empty => 0,
+            BitRangeNode::Branch { size: s, .. } => s.get(),
+        }
+    }
+}
+
+impl TreeAlgebra<bool> for BitRangeNode {
+    fn combine(input: TreeF<bool, BitRangeNode>) -> BitRangeNode {
+        match input {
+            TreeF::Empty => {
+                BitRangeNode::Empty
+            },
+            TreeF::Branch { val, left, right } => {
+                stats::record(|stats| stats.allocations += 2);
+                BitRangeNode::Branch {
+                    here: val,
+                    size: Count::new(get_size(&left) + get_size(&right) + 1),
+                    ones: Count::new(get_ones(&left) + get_ones(&right) + val as usize),
+                    reversed: false,
+                    pending: BitOp::Keep,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            }
+        }
+    }
+}
+
+impl TreeCoalgebra<bool> for BitRangeNode {
+    fn separate(input: BitRangeNode) -> TreeF<bool, BitRangeNode> {
+        match input {
+            BitRangeNode::Empty => {
+                TreeF::Empty
+            },
+            BitRangeNode::Branch {here, reversed, pending, left, right, ..} => {
+                let (left, right) = if pending == BitOp::Keep {
+                    (*left, *right)
+                } else {
+                    (BitRangeNode::updated(*left, pending), BitRangeNode::updated(*right, pending))
+                };
+                if reversed {
+                    TreeF::Branch {
+                        val: here,
+                        left: Reversible::reversed(right),
+                        right: Reversible::reversed(left),
+                    }
+                } else {
+                    TreeF::Branch {
+                        val: here,
+                        left: left,
+                        right: right,
+                    }
+                }
+            },
+        }
+    }
+    fn is_branch(input: &BitRangeNode) -> bool {
+        match *input {
+            BitRangeNode::Empty => false,
+            BitRangeNode::Branch {..} => true,
+        }
+    }
+}
+
+pub(crate) fn end<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(root: B) -> TreeZipper<A, B> {
+    let mut node = root;
+    let mut path = Vec::new();
+    loop {
+        match TreeCoalgebra::separate(node) {
+            TreeF::Empty => {
+                node = TreeAlgebra::combine(TreeF::Empty);
+                break;
+            },
+            TreeF::Branch {val, left, right} => {
+                path.push(TreeZipperStep {
+                    direction: Direction::Right,
+                    parent_val: val,
+                    sibling: left,
+                });
+                node = right;
+            }
+        }
+    }
+    stats::record_path(path.len());
+    TreeZipper {
+        here: node,
+        path: path,
+    }
+}
+
+// Joins two trees where every element of `left` precedes every element of
+// `right`. The last element of `left` is splayed to its root, which leaves an
+// empty right subtree to hang `right` from.
+pub(crate) fn join<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(left: B, right: B) -> B {
+    if !TreeCoalgebra::is_branch(&left) {
+        return right;
+    }
+    match TreeCoalgebra::separate(zip_tree(splay(end(left)))) {
+        TreeF::Empty => right,
+        TreeF::Branch { val, left, .. } => {
+            TreeAlgebra::combine(TreeF::Branch {
+                val: val,
+                left: left,
+                right: right,
+            })
+        },
+    }
+}
+
+// Builds a tree of height about log2(n) holding the next `n` values from
+// `next` in order. Recursion only goes as deep as the tree it builds.
+pub(crate) fn build_balanced<A, B, E, F>(n: usize, next: &mut F) -> Result<B, E>
+    where B: TreeAlgebra<A>, F: FnMut() -> Result<A, E> {
+    if n == 0 {
+        return Ok(TreeAlgebra::combine(TreeF::Empty));
+    }
+    let left = build_balanced(n / 2, next)?;
+    let val = next()?;
+    let right = build_balanced(n - n / 2 - 1, next)?;
+    Ok(TreeAlgebra::combine(TreeF::Branch {
+        val: val,
+        left: left,
+        right: right,
+    }))
+}
+
+pub(crate) fn find_index<A, B: TreeCoalgebra<A> + TreeAlgebra<A> + SubtreeSize>(root: B, index: usize) -> TreeZipper<A, B> {
+    let mut node = root;
+    let mut remaining = index;
+    let mut path = Vec::new();
+    loop {
+        match TreeCoalgebra::separate(node) {
+            TreeF::Empty => {
+                node = TreeAlgebra::combine(TreeF::Empty);
+                break;
+            },
+            TreeF::Branch { val, left, right } => {
+                let left_size = get_size(&left);
+                match left_size.cmp(&remaining) {
+                    Ordering::Less => {
+                        path.push(TreeZipperStep {
+                            direction: Direction::Right,
+                            parent_val: val,
+                            sibling: left,
+                        });
+                        node = right;
+                        remaining = remaining - left_size - 1;
+                    },
+                    Ordering::Equal => {
+                        node = TreeAlgebra::combine(TreeF::Branch {val: val, left: left, right: right});
+                        break;
+                    },
+                    Ordering::Greater => {
+                        path.push(TreeZipperStep {
+                            direction: Direction::Left,
+                            parent_val: val,
+                            sibling: right,
+                        });
+                        node = left;
+                    },
+                }
+            },
+        }
+    }
+    stats::record_path(path.len());
+    TreeZipper {
+        path: path,
+        here: node,
+    }
+}
+
+pub(crate) fn isolate_interval<A, B: TreeCoalgebra<A> + TreeAlgebra<A> + SubtreeSize>(root: B, index_start: usize, index_end: usize, strategy: SplayStrategy) -> TreeZipper<A, B> {
+    let cur_root = root;
+    // Clamp to the sequence; an inverted interval is the empty one at its
+    // start.
+    let index_start = index_start.min(get_size(&cur_root));
+    let index_end = index_end.max(index_start);
+    if index_start == 0 {
+        if index_end >= get_size(&cur_root) {
+            root_zipper(cur_root)
+        } else {
+            left_zipper(root_zipper(splay_index(cur_root, index_end, strategy)))
+        }
+    } else {
+        if index_end >= get_size(&cur_root) {
+            right_zipper(root_zipper(splay_index(cur_root, index_start - 1, strategy)))
+        } else {
+            // Bring the element before the interval to the root, then the
+            // element after it to the root of the right subtree. What is left
+            // between them is exactly the interval.
+            let zipper = right_zipper(root_zipper(splay_index(cur_root, index_start - 1, strategy)));
+            let here = splay_index(zipper.here, index_end - index_start, strategy);
+            left_zipper(TreeZipper {
+                path: zipper.path,
+                here: here,
+            })
+        }
+    }
+}
+
+// Why an index or range was rejected by the checked sequence operations.
+// Ranges must lie within the sequence, so an end past it is out of bounds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexError {
+    OutOfBounds { index: usize, len: usize },
+    InvertedRange { start: usize, end: usize },
+}
+
+impl fmt::Display for IndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            IndexError::OutOfBounds { index, len } => write!(f, "index {} is out of bounds for length {}", index, len),
+            IndexError::InvertedRange { start, end } => write!(f, "range starts at {} but ends at {}", start, end),
+        }
+    }
+}
+
+impl Error for IndexError {}
+
+fn check_index(index: usize, len: usize) -> Result<(), IndexError> {
+    if index < len {
+        Ok(())
+    } else {
+        Err(IndexError::OutOfBounds { index: index, len: len })
+    }
+}
+
+// Insertion points run from the front up to `len` itself, the end.
+fn check_insert_index(index: usize, len: usize) -> Result<(), IndexError> {
+    if index <= len {
+        Ok(())
+    } else {
+        Err(IndexError::OutOfBounds { index: index, len: len })
+    }
+}
+
+// Turns any range of indices into half-open bounds within `len` elements.
+fn check_range<R: RangeBounds<usize>>(range: R, len: usize) -> Result<(usize, usize), IndexError> {
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.checked_add(1).ok_or(IndexError::OutOfBounds { index: start, len: len })?,
+        Bound::Unbounded => 0,
+    };
+    // Errors report the end as the caller wrote it: an included end is the
+    // last index of the range, not one past it.
+    let (given, end) = match range.end_bound() {
+        Bound::Included(&end) => (end, end.checked_add(1).ok_or(IndexError::OutOfBounds { index: end, len: len })?),
+        Bound::Excluded(&end) => (end, end),
+        Bound::Unbounded => (len, len),
+    };
+    if start > end {
+        Err(IndexError::InvertedRange { start: start, end: given })
+    } else if end > len {
+        Err(IndexError::OutOfBounds { index: given, len: len })
+    } else {
+        Ok((start, end))
+    }
+}
+
+#[derive(Debug)]
+pub struct BitRange {
+    root: BitRangeNode,
+    strategy: SplayStrategy,
+    policy: SplayPolicy,
+    stats: Option<Stats>,
+}
+
+impl BitRange {
+    pub fn new(n: usize) -> BitRange {
+        BitRange::with_strategy(n, SplayStrategy::BottomUp)
+    }
+
+    pub fn with_strategy(n: usize, strategy: SplayStrategy) -> BitRange {
+        let mut root = BitRangeNode::Empty;
+        for _ in 0..n {
+            let mut zipper = end(root);
+            zipper.here = TreeAlgebra::combine(TreeF::Branch {
+                val: false,
+                left: BitRangeNode::Empty,
+                right: BitRangeNode::Empty,
+            });
+            root = zip_tree(splay(zipper));
+        }
+        BitRange {
+            root: root,
+            strategy: strategy,
+            policy: SplayPolicy::Full,
+            stats: None,
+        }
+    }
+
+    pub fn set_policy(self: &mut BitRange, policy: SplayPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn root(self: &BitRange) -> &BitRangeNode {
+        &self.root
+    }
+
+    // Starts counting rotations, path lengths and allocations from zero.
+    pub fn enable_stats(self: &mut BitRange) {
+        self.stats = Some(Stats::new());
+    }
+
+    pub fn stats(self: &BitRange) -> Option<&Stats> {
+        self.stats.as_ref()
+    }
+
+    fn observed<R, F: FnOnce(&mut BitRange) -> R>(self: &mut BitRange, f: F) -> R {
+        let (result, stats) = stats::collect(self.stats.clone(), || f(&mut *self));
+        self.stats = stats;
+        result
+    }
+
+    // Splays the element at `index` to the root, returning whether there was
+    // one.
+    fn splay_index(self: &mut BitRange, index: usize) -> bool {
+        let old_root = mem::replace(&mut self.root, BitRangeNode::Empty);
+        let in_range = index < get_size(&old_root);
+        self.root = splay_index(old_root, index, self.strategy);
+        in_range
+    }
+
+    // Runs `f` on the node at `index` (or on an empty node if there is none)
+    // and restructures the tree according to the policy.
+    fn access<R, F: FnOnce(&mut BitRangeNode) -> R>(self: &mut BitRange, index: usize, read: bool, f: F) -> R {
+        if self.policy.splays_fully(read) {
+            if self.splay_index(index) {
+                f(&mut self.root)
+            } else {
+                f(&mut BitRangeNode::Empty)
+            }
+        } else {
+            let old_root = mem::replace(&mut self.root, BitRangeNode::Empty);
+            let mut zipper = find_index(old_root, index);
+            let result = f(&mut zipper.here);
+            self.root = adjust(zipper, self.policy, read);
+            result
+        }
+    }
+
+    // Does nothing if `index` is out of range; `try_set` reports it instead.
+    pub fn set(self: &mut BitRange, index: usize, val: bool) {
+        self.observed(|range| {
+            range.access(index, false, |node| {
+                if let BitRangeNode::Branch { ref mut here, ref mut ones, .. } = *node {
+                    *ones = Count::new(ones.get() + val as usize - *here as usize);
+                    *here = val;
+                }
+            })
+        })
+    }
+
+    // None if `index` is out of range.
+    pub fn get(self: &mut BitRange, index: usize) -> Option<bool> {
+        self.observed(|range| {
+            range.access(index, true, |node| {
+                match *node {
+                    BitRangeNode::Empty => None,
+                    BitRangeNode::Branch { here, .. } => Some(here),
+                }
+            })
+        })
+    }
+
+    pub fn reverse_range(self: &mut BitRange, index_start: usize, index_end: usize) {
+        self.observed(|range| {
+            let tmp_root = mem::replace(&mut range.root, BitRangeNode::Empty);
+            let mut zipper = isolate_interval(tmp_root, index_start, index_end, range.strategy);
+            zipper.here = Reversible::reversed(zipper.here);
+            range.root = zip_tree(zipper);
+        })
+    }
+
+    pub fn len(self: &BitRange) -> usize {
+        get_size(&self.root)
+    }
+
+    pub fn is_empty(self: &BitRange) -> bool {
+        self.len() == 0
+    }
+
+    // Inserts `val` so that it ends up at position `index`, shifting the
+    // following bits up by one. Indices outside the range are clamped.
+    pub fn insert(self: &mut BitRange, index: usize, val: bool) {
+        self.observed(|range| {
+            let tmp_root = mem::replace(&mut range.root, BitRangeNode::Empty);
+            let mut zipper = isolate_interval(tmp_root, index, index, range.strategy);
+            zipper.here = TreeAlgebra::combine(TreeF::Branch {
+                val: val,
+                left: BitRangeNode::Empty,
+                right: BitRangeNode::Empty,
+            });
+            range.root = zip_tree(splay(zipper));
+        })
+    }
+
+    pub fn remove(self: &mut BitRange, index: usize) -> Option<bool> {
+        self.observed(|range| {
+            if index >= range.len() {
+                return None;
+            }
+            let tmp_root = mem::replace(&mut range.root, BitRangeNode::Empty);
+            let mut zipper = isolate_interval(tmp_root, index, index + 1, range.strategy);
+            let removed = match mem::replace(&mut zipper.here, BitRangeNode::Empty) {
+                BitRangeNode::Empty => None,
+                BitRangeNode::Branch { here, .. } => Some(here),
+            };
+            range.root = zip_tree(zipper);
+            removed
+        })
+    }
+
+    // Counts the set bits in [index_start, index_end).
+    pub fn count_ones(self: &mut BitRange, index_start: usize, index_end: usize) -> usize {
+        self.observed(|range| {
+            let tmp_root = mem::replace(&mut range.root, BitRangeNode::Empty);
+            let zipper = isolate_interval(tmp_root, index_start, index_end, range.strategy);
+            let ones = get_ones(&zipper.here);
+            range.root = zip_tree(zipper);
+            ones
+        })
+    }
+
+    pub fn flip_range(self: &mut BitRange, index_start: usize, index_end: usize) {
+        self.observed(|range| {
+            range.update_range(index_start, index_end, BitOp::Flip);
+        })
+    }
+
+    pub fn fill_range(self: &mut BitRange, index_start: usize, index_end: usize, val: bool) {
+        self.observed(|range| {
+            range.update_range(index_start, index_end, BitOp::Fill(val));
+        })
+    }
+
+    fn update_range(self: &mut BitRange, index_start: usize, index_end: usize, op: BitOp) {
+        let tmp_root = mem::replace(&mut self.root, BitRangeNode::Empty);
+        let mut zipper = isolate_interval(tmp_root, index_start, index_end, self.strategy);
+        zipper.here = BitRangeNode::updated(zipper.here, op);
+        self.root = zip_tree(zipper);
+    }
+
+    // Cuts out [index_start, index_end) and reinserts it so that it starts at
+    // position `to` of the remaining bits.
+    pub fn move_range(self: &mut BitRange, index_start: usize, index_end: usize, to: usize) {
+        self.observed(|range| {
+            let tmp_root = mem::replace(&mut range.root, BitRangeNode::Empty);
+            let mut zipper = isolate_interval(tmp_root, index_start, index_end, range.strategy);
+            let block = mem::replace(&mut zipper.here, BitRangeNode::Empty);
+            let mut zipper = isolate_interval(zip_tree(zipper), to, to, range.strategy);
+            zipper.here = block;
+            range.root = zip_tree(zipper);
+        })
+    }
+
+    // Checked counterparts of the operations above. Instead of ignoring or
+    // clamping indices outside the sequence, they leave it unchanged and say
+    // what was wrong.
+
+    pub fn try_set(self: &mut BitRange, index: usize, val: bool) -> Result<(), IndexError> {
+        check_index(index, self.len())?;
+        self.set(index, val);
+        Ok(())
+    }
+
+    // Inserting at the length appends.
+    pub fn try_insert(self: &mut BitRange, index: usize, val: bool) -> Result<(), IndexError> {
+        check_insert_index(index, self.len())?;
+        self.insert(index, val);
+        Ok(())
+    }
+
+    pub fn try_reverse_range(self: &mut BitRange, index_start: usize, index_end: usize) -> Result<(), IndexError> {
+        self.reverse(index_start..index_end)
+    }
+
+    // Range versions, such as `reverse(2..7)` or `flip(..)`.
+    pub fn reverse<R: RangeBounds<usize>>(self: &mut BitRange, range: R) -> Result<(), IndexError> {
+        let (start, end) = check_range(range, self.len())?;
+        self.reverse_range(start, end);
+        Ok(())
+    }
+
+    pub fn count<R: RangeBounds<usize>>(self: &mut BitRange, range: R) -> Result<usize, IndexError> {
+        let (start, end) = check_range(range, self.len())?;
+        Ok(self.count_ones(start, end))
+    }
+
+    pub fn flip<R: RangeBounds<usize>>(self: &mut BitRange, range: R) -> Result<(), IndexError> {
+        let (start, end) = check_range(range, self.len())?;
+        self.flip_range(start, end);
+        Ok(())
+    }
+
+    pub fn fill<R: RangeBounds<usize>>(self: &mut BitRange, range: R, val: bool) -> Result<(), IndexError> {
+        let (start, end) = check_range(range, self.len())?;
+        self.fill_range(start, end, val);
+        Ok(())
+    }
+
+    pub fn first(self: &mut BitRange) -> Option<bool> {
+        self.get(0)
+    }
+
+    pub fn last(self: &mut BitRange) -> Option<bool> {
+        match self.len() {
+            0 => None,
+            n => self.get(n - 1),
+        }
+    }
+
+    pub fn swap(self: &mut BitRange, a: usize, b: usize) -> Result<(), IndexError> {
+        check_index(a, self.len())?;
+        check_index(b, self.len())?;
+        let (bit_a, bit_b) = (self.get(a), self.get(b));
+        if bit_a != bit_b {
+            self.flip_range(a, a + 1);
+            self.flip_range(b, b + 1);
+        }
+        Ok(())
+    }
+
+    // The root counts every set bit, so this needs no walk.
+    pub fn contains(self: &BitRange, bit: bool) -> bool {
+        let ones = get_ones(&self.root);
+        if bit { ones > 0 } else { ones < self.len() }
+    }
+
+    pub fn position<P: FnMut(bool) -> bool>(self: &BitRange, mut pred: P) -> Option<usize> {
+        let mut index = 0;
+        let result = self.try_for_each(|bit| {
+            if pred(bit) {
+                return Err(index);
+            }
+            index += 1;
+            Ok(())
+        });
+        result.err()
+    }
+
+    pub fn to_vec(self: &BitRange) -> Vec<bool> {
+        let mut bits = Vec::with_capacity(self.len());
+        let result: Result<(), ()> = self.try_for_each(|bit| {
+            bits.push(bit);
+            Ok(())
+        });
+        result.unwrap();
+        bits
+    }
+
+    // Calls `f` on every bit in order, stopping at the first error.
+    pub(crate) fn try_for_each<E, F: FnMut(bool) -> Result<(), E>>(self: &BitRange, mut f: F) -> Result<(), E> {
+        // Walk the stored tree in order, tracking the reversals and updates
+        // that ancestors still owe to the nodes below them.
+        let mut stack = Vec::new();
+        let mut node = &self.root;
+        let mut flipped = false;
+        let mut op = BitOp::Keep;
+        loop {
+            while let BitRangeNode::Branch { ref left, ref right, here, reversed, pending, .. } = *node {
+                let child_flipped = flipped ^ reversed;
+                let child_op = pending.then(op);
+                let (first, second) = if child_flipped { (right, left) } else { (left, right) };
+                stack.push((op.apply(here), &**second, child_flipped, child_op));
+                node = first;
+                flipped = child_flipped;
+                op = child_op;
+            }
+            match stack.pop() {
+                None => return Ok(()),
+                Some((bit, next, next_flipped, next_op)) => {
+                    f(bit)?;
+                    node = next;
+                    flipped = next_flipped;
+                    op = next_op;
+                },
+            }
+        }
+    }
+}
+
+// Appended bits are built into a balanced tree first and joined on in one go.
+impl Extend<bool> for BitRange {
+    fn extend<I: IntoIterator<Item = bool>>(&mut self, iter: I) {
+        let bits: Vec<bool> = iter.into_iter().collect();
+        self.observed(|range| {
+            let mut bits = bits.iter();
+            let result: Result<BitRangeNode, ()> = build_balanced(bits.len(), &mut || Ok(*bits.next().unwrap()));
+            let old_root = mem::replace(&mut range.root, BitRangeNode::Empty);
+            range.root = join(old_root, result.unwrap());
+        })
+    }
+}
+
+impl FromIterator<bool> for BitRange {
+    fn from_iter<I: IntoIterator<Item = bool>>(iter: I) -> BitRange {
+        let mut range = BitRange::new(0);
+        range.extend(iter);
+        range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty() -> TreeNode<i32> {
+        TreeNode(TreeF::Empty)
+    }
+
+    fn node(val: i32, left: TreeNode<i32>, right: TreeNode<i32>) -> TreeNode<i32> {
+        TreeAlgebra::combine(TreeF::Branch {
+            val: val,
+            left: left,
+            right: right,
+        })
+    }
+
+    fn leaf(val: i32) -> TreeNode<i32> {
+        node(val, empty(), empty())
+    }
+
+    // Renders a tree as nested parentheses, e.g. "(1 2 (3))" for a root 2
+    // with children 1 and 3.
+    fn shape(tree: &TreeNode<i32>) -> String {
+        match *tree {
+            TreeNode(TreeF::Empty) => String::from("."),
+            TreeNode(TreeF::Branch { val, ref left, ref right }) => {
+                match (&left.0, &right.0) {
+                    (&TreeF::Empty, &TreeF::Empty) => format!("({})", val),
+                    _ => format!("({} {} {})", shape(left), val, shape(right)),
+                }
+            },
+        }
+    }
+
+    fn bits(node: BitRangeNode) -> Vec<bool> {
+        let mut out = Vec::new();
+        collect_bits(node, &mut out);
+        out
+    }
+
+    fn collect_bits(node: BitRangeNode, out: &mut Vec<bool>) {
+        if let TreeF::Branch { val, left, right } = TreeCoalgebra::separate(node) {
+            collect_bits(left, out);
+            out.push(val);
+            collect_bits(right, out);
+        }
+    }
+
+    // Bits follow an irregular pattern so that misplaced elements show up when
+    // comparing against expected_bits.
+    fn pattern_range(n: usize) -> BitRangeNode {
+        let mut root = BitRangeNode::Empty;
+        for i in 0..n {
+            let mut zipper = end(root);
+            zipper.here = TreeAlgebra::combine(TreeF::Branch {
+                val: i % 3 == 0 || i % 5 == 1,
+                left: BitRangeNode::Empty,
+                right: BitRangeNode::Empty,
+            });
+            root = zip_tree(splay(zipper));
+        }
+        root
+    }
+
+    fn expected_bits(n: usize) -> Vec<bool> {
+        (0..n).map(|i| i % 3 == 0 || i % 5 == 1).collect()
+    }
+
+    #[test]
+    fn find_records_path() {
+        let tree = node(4, node(2, leaf(1), leaf(3)), leaf(5));
+        let zipper = find(tree, &3);
+        let directions: Vec<&Direction> = zipper.path.iter().map(|step| &step.direction).collect();
+        assert_eq!(directions, vec![&Direction::Left, &Direction::Right]);
+        assert_eq!(zipper.path[0].parent_val, 4);
+        assert_eq!(shape(&zipper.path[0].sibling), "(5)");
+        assert_eq!(zipper.path[1].parent_val, 2);
+        assert_eq!(shape(&zipper.path[1].sibling), "(1)");
+        assert_eq!(shape(&zipper.here), "(3)");
+    }
+
+    #[test]
+    fn find_missing_stops_at_empty() {
+        let tree = node(4, node(2, leaf(1), leaf(3)), leaf(5));
+        let zipper = find(tree, &6);
+        assert_eq!(zipper.path.len(), 2);
+        assert!(!TreeCoalgebra::is_branch(&zipper.here));
+        assert_eq!(shape(&zip_tree(zipper)), "(((1) 2 (3)) 4 (5))");
+    }
+
+    #[test]
+    fn splay_step_zig() {
+        let tree = node(4, node(2, leaf(1), leaf(3)), leaf(5));
+        let zipper = splay_step(find(tree, &2));
+        assert!(zipper.path.is_empty());
+        assert_eq!(shape(&zipper.here), "((1) 2 ((3) 4 (5)))");
+    }
+
+    #[test]
+    fn splay_step_zig_zig() {
+        let tree = node(6, node(4, node(2, leaf(1), leaf(3)), leaf(5)), leaf(7));
+        let zipper = splay_step(find(tree, &2));
+        assert!(zipper.path.is_empty());
+        assert_eq!(shape(&zipper.here), "((1) 2 ((3) 4 ((5) 6 (7))))");
+    }
+
+    #[test]
+    fn splay_step_zig_zag() {
+        let tree = node(6, node(2, leaf(1), node(4, leaf(3), leaf(5))), leaf(7));
+        let zipper = splay_step(find(tree, &4));
+        assert!(zipper.path.is_empty());
+        assert_eq!(shape(&zipper.here), "(((1) 2 (3)) 4 ((5) 6 (7)))");
+    }
+
+    #[test]
+    fn splay_step_zag_zig() {
+        let tree = node(2, leaf(1), node(6, node(4, leaf(3), leaf(5)), leaf(7)));
+        let zipper = splay_step(find(tree, &4));
+        assert!(zipper.path.is_empty());
+        assert_eq!(shape(&zipper.here), "(((1) 2 (3)) 4 ((5) 6 (7)))");
+    }
+
+    #[test]
+    fn splay_step_zag_zag() {
+        let tree = node(2, leaf(1), node(4, leaf(3), node(6, leaf(5), leaf(7))));
+        let zipper = splay_step(find(tree, &6));
+        assert!(zipper.path.is_empty());
+        assert_eq!(shape(&zipper.here), "((((1) 2 (3)) 4 (5)) 6 (7))");
+    }
+
+    #[test]
+    fn splay_step_keeps_rest_of_path() {
+        let tree = node(10, node(8, node(6, node(4, leaf(3), leaf(5)), leaf(7)), leaf(9)), leaf(11));
+        let zipper = splay_step(find(tree, &4));
+        assert_eq!(zipper.path.len(), 1);
+        assert_eq!(zipper.path[0].parent_val, 10);
+        assert_eq!(shape(&zipper.here), "((3) 4 ((5) 6 ((7) 8 (9))))");
+        assert_eq!(shape(&zip_tree(zipper)), "(((3) 4 ((5) 6 ((7) 8 (9)))) 10 (11))");
+    }
+
+    #[test]
+    fn rotate_zipper_left_child() {
+        let tree = node(4, node(2, leaf(1), leaf(3)), leaf(5));
+        let zipper = rotate_zipper(find(tree, &2));
+        assert!(zipper.path.is_empty());
+        assert_eq!(shape(&zipper.here), "((1) 2 ((3) 4 (5)))");
+    }
+
+    #[test]
+    fn rotate_zipper_right_child() {
+        let tree = node(2, leaf(1), node(4, leaf(3), leaf(5)));
+        let zipper = rotate_zipper(find(tree, &4));
+        assert!(zipper.path.is_empty());
+        assert_eq!(shape(&zipper.here), "(((1) 2 (3)) 4 (5))");
+    }
+
+    #[test]
+    fn rotate_zipper_only_moves_one_level() {
+        let tree = node(6, node(4, node(2, leaf(1), leaf(3)), leaf(5)), leaf(7));
+        let zipper = rotate_zipper(find(tree, &2));
+        assert_eq!(zipper.path.len(), 1);
+        assert_eq!(shape(&zip_tree(zipper)), "(((1) 2 ((3) 4 (5))) 6 (7))");
+    }
+
+    #[test]
+    fn rotate_zipper_at_root_or_empty_is_identity() {
+        let tree = node(2, leaf(1), leaf(3));
+        assert_eq!(shape(&zip_tree(rotate_zipper(root_zipper(tree)))), "((1) 2 (3))");
+        let zipper = rotate_zipper(find(node(2, leaf(1), leaf(3)), &0));
+        assert_eq!(zipper.path.len(), 2);
+        assert_eq!(shape(&zip_tree(zipper)), "((1) 2 (3))");
+    }
+
+    #[test]
+    fn splay_missing_value_brings_neighbour_up() {
+        let tree = node(6, node(4, node(2, leaf(1), leaf(3)), leaf(5)), leaf(7));
+        let root = zip_tree(splay(find(tree, &0)));
+        assert_eq!(shape(&root), "(. 1 ((. 2 ((3) 4 (5))) 6 (7)))");
+    }
+
+    fn isolate(n: usize, start: usize, end: usize, strategy: SplayStrategy) -> Vec<bool> {
+        let zipper = isolate_interval(pattern_range(n), start, end, strategy);
+        bits(zipper.here)
+    }
+
+    #[test]
+    fn isolate_interval_edge_cases() {
+        let n = 10;
+        let all = expected_bits(n);
+        for &strategy in &[SplayStrategy::BottomUp, SplayStrategy::TopDown] {
+            assert_eq!(isolate(n, 0, n, strategy), all);
+            assert_eq!(isolate(n, 0, n + 5, strategy), all);
+            assert_eq!(isolate(n, 0, 4, strategy), &all[0..4]);
+            assert_eq!(isolate(n, 6, n, strategy), &all[6..]);
+            assert_eq!(isolate(n, 3, 7, strategy), &all[3..7]);
+            assert_eq!(isolate(n, 4, 5, strategy), &all[4..5]);
+            assert!(isolate(n, 4, 4, strategy).is_empty());
+            assert!(isolate(n, 7, 3, strategy).is_empty());
+            assert!(isolate(n, 0, 0, strategy).is_empty());
+            assert!(isolate(n, n, n, strategy).is_empty());
+            assert!(isolate(n, n + 3, n + 5, strategy).is_empty());
+            assert!(isolate(0, 0, 3, strategy).is_empty());
+        }
+    }
+
+    #[test]
+    fn checked_operations_reject_bad_indices() {
+        let mut range = BitRange::new(6);
+        assert_eq!(range.try_set(6, true), Err(IndexError::OutOfBounds { index: 6, len: 6 }));
+        assert_eq!(range.try_set(1, true), Ok(()));
+        assert_eq!(range.try_insert(7, true), Err(IndexError::OutOfBounds { index: 7, len: 6 }));
+        assert_eq!(range.try_insert(6, true), Ok(()));
+        assert_eq!(range.try_reverse_range(4, 2), Err(IndexError::InvertedRange { start: 4, end: 2 }));
+        assert_eq!(range.reverse(3..=7), Err(IndexError::OutOfBounds { index: 7, len: 7 }));
+        assert_eq!(range.to_vec(), vec![false, true, false, false, false, false, true]);
+        assert_eq!(range.reverse(..2), Ok(()));
+        assert_eq!(range.fill(4.., true), Ok(()));
+        assert_eq!(range.flip(2..4), Ok(()));
+        assert_eq!(range.to_vec(), vec![true, false, true, true, true, true, true]);
+        assert_eq!(range.count(..), Ok(6));
+        assert_eq!(range.count(1..1), Ok(0));
+        assert_eq!(check_range((Bound::Excluded(2), Bound::Excluded(2)), 5), Err(IndexError::InvertedRange { start: 3, end: 2 }));
+    }
+
+    #[test]
+    fn slice_like_access() {
+        let mut range: BitRange = vec![false, false, true].into_iter().collect();
+        assert_eq!((range.first(), range.last()), (Some(false), Some(true)));
+        assert!(range.contains(true) && range.contains(false));
+        assert_eq!(range.position(|bit| bit), Some(2));
+        assert_eq!(range.swap(0, 2), Ok(()));
+        assert_eq!(range.swap(0, 3), Err(IndexError::OutOfBounds { index: 3, len: 3 }));
+        range.extend(vec![true, true]);
+        range.reverse(1..).unwrap();
+        assert_eq!(range.to_vec(), vec![true, true, true, false, false]);
+        assert_eq!(range.position(|bit| !bit), Some(3));
+        range.fill(.., true).unwrap();
+        assert!(!range.contains(false));
+        assert!(range.validate().is_ok());
+
+        let mut empty = BitRange::new(0);
+        assert_eq!((empty.first(), empty.last()), (None, None));
+        assert!(!empty.contains(true) && !empty.contains(false));
+    }
+
+    #[test]
+    fn counts_fit_in_48_bits() {
+        for &n in &[0, 1, 65535, 65536, 1 << 40, Count::MAX as usize] {
+            assert_eq!(Count::new(n).get(), n);
+        }
+        assert!(Count::new(65536) > Count::new(1));
+        assert!(Count::new(1 << 32) > Count::new(0xFFFF_FFFF));
+        assert!(Count::new(0x1_0000_0001) < Count::new(0x2_0000_0000));
+        if cfg!(target_pointer_width = "64") {
+            assert_eq!(mem::size_of::<BitRangeNode>(), 32);
+        }
+    }
+
+    #[test]
+    fn isolate_interval_keeps_the_rest_of_the_tree() {
+        for start in 0..8 {
+            for end in 0..8 {
+                let zipper = isolate_interval(pattern_range(7), start, end, SplayStrategy::BottomUp);
+                assert_eq!(bits(zip_tree(zipper)), expected_bits(7));
+            }
+        }
+    }
+
+    #[test]
+    fn stats_count_steps_and_paths() {
+        let mut tree = SplayTree::new();
+        tree.insert(1);
+        assert_eq!(tree.stats(), None);
+        tree.enable_stats();
+        tree.insert(2);
+        tree.insert(3);
+        tree.contains(1);
+        let stats = tree.stats().unwrap().clone();
+        assert_eq!((stats.operations, stats.zigs, stats.zig_zigs, stats.zig_zags), (3, 2, 1, 0));
+        assert_eq!((stats.accesses, stats.path_length, stats.max_depth), (3, 4, 2));
+        assert_eq!(stats.rotations(), 4);
+        assert!(stats.allocations > 0);
+    }
+
+    // Keys whose comparisons panic once either side is negative.
+    #[derive(Debug, PartialEq, Eq)]
+    struct Touchy(i32);
+
+    impl PartialOrd for Touchy {
+        fn partial_cmp(&self, other: &Touchy) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Touchy {
+        fn cmp(&self, other: &Touchy) -> Ordering {
+            assert!(self.0 >= 0 && other.0 >= 0, "negative key");
+            self.0.cmp(&other.0)
+        }
+    }
+
+    #[test]
+    fn stats_survive_a_panicking_operation() {
+        let mut tree = SplayTree::new();
+        tree.enable_stats();
+        tree.insert(Touchy(1));
+        tree.insert(Touchy(2));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| tree.insert(Touchy(-1))));
+        assert!(result.is_err());
+        assert_eq!(tree.stats().unwrap().operations, 2);
+        // Nothing is left installed to swallow the next tree's counts.
+        let mut other = SplayTree::new();
+        other.enable_stats();
+        other.insert(Touchy(3));
+        other.insert(Touchy(4));
+        assert_eq!((other.stats().unwrap().operations, other.stats().unwrap().zigs), (2, 1));
+    }
+
+    #[test]
+    fn stats_cover_top_down_splaying() {
+        let mut range = BitRange::with_strategy(8, SplayStrategy::TopDown);
+        range.enable_stats();
+        range.get(0);
+        range.reverse_range(2, 5);
+        let stats = range.stats().unwrap();
+        assert_eq!(stats.operations, 2);
+        assert_eq!(stats.max_depth, 7);
+        assert!(stats.zigs + stats.zig_zigs > 0);
+        assert_eq!(stats.zig_zags, 0);
+    }
+
+    #[test]
+    fn reverse_empty_range_is_a_no_op() {
+        let mut range = BitRange::new(4);
+        range.set(3, true);
+        range.reverse_range(3, 3);
+        range.set(0, true);
+        range.reverse_range(1, 1);
+        range.reverse_range(3, 1);
+        let got: Vec<Option<bool>> = (0..4).map(|i| range.get(i)).collect();
+        assert_eq!(got, vec![Some(true), Some(false), Some(false), Some(true)]);
+        range.validate().unwrap();
+    }
+}
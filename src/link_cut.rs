@@ -0,0 +1,334 @@
+use std::mem;
+
+use {TreeF, TreeAlgebra};
+
+// Sleator and Tarjan's link-cut trees: a forest of rooted trees under
+// linking, cutting and rerooting, with aggregates over the path between any
+// two nodes, all in amortized O(log n).
+//
+// Each tree is split into preferred paths, and every path is kept in a splay
+// tree ordered by depth. A path's splay tree hangs off the node above the
+// path's top through a path-parent pointer, stored in the same `parent` field
+// as ordinary splay parents: a node is the root of its splay tree when its
+// parent does not list it as a child. Splaying needs those upward links, so
+// the nodes live in an arena with their own rotations and splay loop; none
+// of the zipper's splaying, nor `Reversible`, applies to them. Rerooting
+// reverses a whole path with a lazy flag pushed down before each splay,
+// much as `BitRange` does with its own.
+//
+// Aggregates are annotations computed with `TreeAlgebra`, as in
+// `AnnotatedTreeNode`. Rerooting reverses paths without recomputing them, so
+// an aggregate must not depend on the order of the path: sums, minima and
+// maxima are fine, but not, say, a hash of the sequence.
+
+const NIL: usize = usize::MAX;
+
+#[derive(Debug)]
+struct LinkCutNode<A, B> {
+    val: A,
+    annotation: B,
+    reversed: bool,
+    left: usize,
+    right: usize,
+    parent: usize,
+}
+
+#[derive(Debug)]
+pub struct LinkCutForest<A, B> {
+    nodes: Vec<LinkCutNode<A, B>>,
+    path: Vec<usize>,
+}
+
+impl<A: Clone, B: TreeAlgebra<A> + Copy> LinkCutForest<A, B> {
+    pub fn new() -> LinkCutForest<A, B> {
+        LinkCutForest {
+            nodes: Vec::new(),
+            path: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    // Adds a node in a tree of its own and returns its index.
+    pub fn add_node(&mut self, val: A) -> usize {
+        let annotation = TreeAlgebra::combine(TreeF::Branch {
+            val: val.clone(),
+            left: TreeAlgebra::combine(TreeF::Empty),
+            right: TreeAlgebra::combine(TreeF::Empty),
+        });
+        self.nodes.push(LinkCutNode {
+            val: val,
+            annotation: annotation,
+            reversed: false,
+            left: NIL,
+            right: NIL,
+            parent: NIL,
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn value(&self, u: usize) -> &A {
+        &self.nodes[u].val
+    }
+
+    pub fn set_value(&mut self, u: usize, val: A) {
+        // With `u` at the root of its splay tree, only its own annotation
+        // depends on the value.
+        self.splay(u);
+        self.nodes[u].val = val;
+        self.update(u);
+    }
+
+    fn annotation(&self, i: usize) -> B {
+        if i == NIL { TreeAlgebra::combine(TreeF::Empty) } else { self.nodes[i].annotation }
+    }
+
+    fn update(&mut self, i: usize) {
+        let annotation = TreeAlgebra::combine(TreeF::Branch {
+            val: self.nodes[i].val.clone(),
+            left: self.annotation(self.nodes[i].left),
+            right: self.annotation(self.nodes[i].right),
+        });
+        self.nodes[i].annotation = annotation;
+    }
+
+    fn toggle(&mut self, i: usize) {
+        if i != NIL {
+            self.nodes[i].reversed = !self.nodes[i].reversed;
+        }
+    }
+
+    // Applies a pending reversal to the children of `i`.
+    fn push(&mut self, i: usize) {
+        if self.nodes[i].reversed {
+            let node = &mut self.nodes[i];
+            node.reversed = false;
+            mem::swap(&mut node.left, &mut node.right);
+            let (left, right) = (node.left, node.right);
+            self.toggle(left);
+            self.toggle(right);
+        }
+    }
+
+    fn is_splay_root(&self, i: usize) -> bool {
+        let p = self.nodes[i].parent;
+        p == NIL || (self.nodes[p].left != i && self.nodes[p].right != i)
+    }
+
+    // Rotates `x` above its parent, which takes over the parent's link to
+    // the node above, splay parent or path-parent alike.
+    fn rotate(&mut self, x: usize) {
+        let p = self.nodes[x].parent;
+        let g = self.nodes[p].parent;
+        if !self.is_splay_root(p) {
+            if self.nodes[g].left == p {
+                self.nodes[g].left = x;
+            } else {
+                self.nodes[g].right = x;
+            }
+        }
+        self.nodes[x].parent = g;
+        let moved = if self.nodes[p].left == x {
+            let moved = self.nodes[x].right;
+            self.nodes[p].left = moved;
+            self.nodes[x].right = p;
+            moved
+        } else {
+            let moved = self.nodes[x].left;
+            self.nodes[p].right = moved;
+            self.nodes[x].left = p;
+            moved
+        };
+        if moved != NIL {
+            self.nodes[moved].parent = p;
+        }
+        self.nodes[p].parent = x;
+        self.update(p);
+        self.update(x);
+    }
+
+    // Splays `x` to the root of its splay tree.
+    fn splay(&mut self, x: usize) {
+        // Pending reversals are pushed down from the top first, so that the
+        // children seen while rotating are the real ones.
+        let mut path = mem::take(&mut self.path);
+        path.clear();
+        let mut i = x;
+        path.push(i);
+        while !self.is_splay_root(i) {
+            i = self.nodes[i].parent;
+            path.push(i);
+        }
+        for &i in path.iter().rev() {
+            self.push(i);
+        }
+        self.path = path;
+        while !self.is_splay_root(x) {
+            let p = self.nodes[x].parent;
+            if !self.is_splay_root(p) {
+                let g = self.nodes[p].parent;
+                let zig_zig = (self.nodes[g].left == p) == (self.nodes[p].left == x);
+                self.rotate(if zig_zig { p } else { x });
+            }
+            self.rotate(x);
+        }
+    }
+
+    // Makes the path from the root of u's tree down to `u` preferred, so that
+    // it forms one splay tree with `u` at its root and nothing deeper.
+    fn access(&mut self, u: usize) {
+        let mut below = NIL;
+        let mut i = u;
+        while i != NIL {
+            self.splay(i);
+            self.nodes[i].right = below;
+            self.update(i);
+            below = i;
+            i = self.nodes[i].parent;
+        }
+        self.splay(u);
+    }
+
+    // Makes `u` the root of its tree.
+    pub fn evert(&mut self, u: usize) {
+        self.access(u);
+        self.toggle(u);
+    }
+
+    pub fn find_root(&mut self, u: usize) -> usize {
+        self.access(u);
+        let mut r = u;
+        loop {
+            self.push(r);
+            match self.nodes[r].left {
+                NIL => break,
+                left => r = left,
+            }
+        }
+        self.splay(r);
+        r
+    }
+
+    pub fn connected(&mut self, u: usize, v: usize) -> bool {
+        u == v || self.find_root(u) == self.find_root(v)
+    }
+
+    // Joins the trees of `u` and `v` with an edge between them, making `v`
+    // the parent of `u`. Returns false, leaving the forest unchanged, if they
+    // are already in the same tree.
+    pub fn link(&mut self, u: usize, v: usize) -> bool {
+        if self.connected(u, v) {
+            return false;
+        }
+        self.evert(u);
+        self.nodes[u].parent = v;
+        true
+    }
+
+    // Removes the edge between `u` and `v`, returning whether there was one.
+    pub fn cut(&mut self, u: usize, v: usize) -> bool {
+        if u == v {
+            return false;
+        }
+        self.evert(u);
+        self.access(v);
+        // The path from u to v is now v's left subtree; they are adjacent
+        // exactly when that subtree is u alone.
+        if self.nodes[v].left != u || self.nodes[u].left != NIL || self.nodes[u].right != NIL {
+            return false;
+        }
+        self.nodes[v].left = NIL;
+        self.nodes[u].parent = NIL;
+        self.update(v);
+        true
+    }
+
+    // The parent of `u` in its tree, as rooted by the latest links and
+    // everts.
+    pub fn parent(&mut self, u: usize) -> Option<usize> {
+        self.access(u);
+        self.push(u);
+        let mut p = self.nodes[u].left;
+        if p == NIL {
+            return None;
+        }
+        loop {
+            self.push(p);
+            match self.nodes[p].right {
+                NIL => break,
+                right => p = right,
+            }
+        }
+        self.splay(p);
+        Some(p)
+    }
+
+    // The annotation of the path from `u` to `v`, both included, or None if
+    // they are in different trees. This reroots u's tree at `u`.
+    pub fn path_aggregate(&mut self, u: usize, v: usize) -> Option<B> {
+        if !self.connected(u, v) {
+            return None;
+        }
+        self.evert(u);
+        self.access(v);
+        Some(self.nodes[v].annotation)
+    }
+}
+
+impl<A: Clone, B: TreeAlgebra<A> + Copy> Default for LinkCutForest<A, B> {
+    fn default() -> LinkCutForest<A, B> {
+        LinkCutForest::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct SumMax(i64, i64);
+
+    impl TreeAlgebra<i64> for SumMax {
+        fn combine(input: TreeF<i64, SumMax>) -> SumMax {
+            match input {
+                TreeF::Empty => SumMax(0, i64::MIN),
+                TreeF::Branch { val, left, right } => SumMax(left.0 + val + right.0, val.max(left.1).max(right.1)),
+            }
+        }
+    }
+
+    #[test]
+    fn links_cuts_and_aggregates() {
+        let mut forest: LinkCutForest<i64, SumMax> = LinkCutForest::new();
+        let nodes: Vec<usize> = (1..=6).map(|v| forest.add_node(v * 10)).collect();
+        // 0 - 1 - 2 - 3, and 4 - 5.
+        assert!(forest.link(nodes[1], nodes[0]));
+        assert!(forest.link(nodes[2], nodes[1]));
+        assert!(forest.link(nodes[3], nodes[2]));
+        assert!(forest.link(nodes[5], nodes[4]));
+        assert!(!forest.link(nodes[0], nodes[3]));
+        assert!(forest.connected(nodes[0], nodes[3]));
+        assert!(!forest.connected(nodes[0], nodes[4]));
+        assert_eq!(forest.path_aggregate(nodes[3], nodes[1]), Some(SumMax(90, 40)));
+        assert_eq!(forest.path_aggregate(nodes[0], nodes[5]), None);
+
+        forest.evert(nodes[2]);
+        assert_eq!(forest.find_root(nodes[0]), nodes[2]);
+        assert_eq!(forest.parent(nodes[0]), Some(nodes[1]));
+        assert_eq!(forest.parent(nodes[2]), None);
+
+        assert!(!forest.cut(nodes[0], nodes[2]));
+        assert!(forest.cut(nodes[2], nodes[1]));
+        assert!(!forest.connected(nodes[0], nodes[3]));
+        assert!(forest.link(nodes[4], nodes[1]));
+        forest.set_value(nodes[5], 1);
+        assert_eq!(forest.path_aggregate(nodes[0], nodes[5]), Some(SumMax(81, 50)));
+        assert_eq!(*forest.value(nodes[5]), 1);
+    }
+}
@@ -1,849 +1,399 @@
-use std::cmp::Ordering;
+#![allow(clippy::redundant_field_names)]
+
+extern crate rust_splay;
+
+use std::env;
 use std::error::Error;
+use std::fmt;
 use std::fs::File;
-use std::io::*;
-use std::mem;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
 
-#[derive(Debug)]
-enum TreeF<A, B> {
-    Empty,
-    Branch { val: A, left: B, right: B },
-}
+mod repl;
 
-trait TreeAlgebra<A> where Self: Sized {
-    fn combine(TreeF<A, Self>) -> Self;
-}
+use rust_splay::BitRange;
+use rust_splay::command::{parse_command, parse_header, argument_column, Command, ParseError, ParseErrorKind, COMMAND_LETTERS};
 
-trait TreeCoalgebra<A> where Self: Sized {
-    fn separate(Self) -> TreeF<A, Self>;
-    fn is_branch(&Self) -> bool;
+// What to do with a command letter the driver does not know about.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnknownCommands {
+    Reject,
+    Skip,
 }
 
 #[derive(Debug)]
-struct TreeNode<A>(TreeF<A, Box<TreeNode<A>>>);
-
-impl<A> TreeAlgebra<A> for TreeNode<A> {
-    fn combine(input: TreeF<A, TreeNode<A>>) -> TreeNode<A> {
-        match input {
-            TreeF::Empty => {
-                TreeNode(TreeF::Empty)
-            },
-            TreeF::Branch { val, left, right } => {
-                TreeNode(TreeF::Branch {
-                    val: val,
-                    left: Box::new(left),
-                    right: Box::new(right),
-                })
-            },
-        }
-    }
-}
-
-impl<A> TreeCoalgebra<A> for TreeNode<A> {
-    fn separate(input: TreeNode<A>) -> TreeF<A, TreeNode<A>> {
-        match input {
-            TreeNode(TreeF::Empty) => {
-                TreeF::Empty
-            },
-            TreeNode(TreeF::Branch { val, left, right }) => {
-                TreeF::Branch {
-                    val: val,
-                    left: *left,
-                    right: *right,
-                }
-            },
-        }
-    }
-    fn is_branch(input: &TreeNode<A>) -> bool {
-        match *input {
-            TreeNode(TreeF::Empty) => false,
-            TreeNode(TreeF::Branch {..}) => true,
-        }
-    }
+enum ErrorKind {
+    Parse(ParseErrorKind),
+    IndexOutOfRange(usize),
+    UnexpectedEof,
 }
 
 #[derive(Debug)]
-struct AnnotatedTreeNode<A, B> {
-    annotation: B,
-    node: TreeF<A, Box<AnnotatedTreeNode<A, B>>>,
-}
-
-impl<A: Clone, B: TreeAlgebra<A> + Copy> TreeAlgebra<A> for AnnotatedTreeNode<A, B> {
-    fn combine(input: TreeF<A, AnnotatedTreeNode<A, B>>) -> AnnotatedTreeNode<A, B> {
-        match input {
-            TreeF::Empty => {
-                AnnotatedTreeNode {
-                    annotation: TreeAlgebra::combine(TreeF::Empty),
-                    node: TreeF::Empty,
-                }
-            },
-            TreeF::Branch { val, left, right } => {
-                let new_ann = TreeAlgebra::combine(TreeF::Branch {
-                    val: val.clone(),
-                    left: left.annotation,
-                    right: right.annotation
-                });
-                AnnotatedTreeNode {
-                    annotation: new_ann,
-                    node: TreeF::Branch {
-                        val: val,
-                        left: Box::new(left),
-                        right: Box::new(right),
-                    }
-                }
-            },
+enum DriverError {
+    Usage(String),
+    OpenInput(io::Error),
+    CreateOutput(io::Error),
+    CreateDot(io::Error),
+    Read(io::Error),
+    Write(io::Error),
+    Input { line: usize, column: usize, kind: ErrorKind },
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ErrorKind::Parse(ref kind) => write!(f, "{}", kind),
+            ErrorKind::IndexOutOfRange(index) => write!(f, "index {} is out of range", index),
+            ErrorKind::UnexpectedEof => write!(f, "unexpected end of input"),
         }
     }
 }
 
-impl<A, B> TreeCoalgebra<A> for AnnotatedTreeNode<A, B> {
-    fn separate(input: AnnotatedTreeNode<A, B>) -> TreeF<A, AnnotatedTreeNode<A, B>> {
-        match input.node {
-            TreeF::Empty => {
-                TreeF::Empty
-            },
-            TreeF::Branch { val, left, right } => {
-                TreeF::Branch {
-                    val: val,
-                    left: *left,
-                    right: *right,
-                }
-            }
-        }
-    }
-    fn is_branch(input: &AnnotatedTreeNode<A, B>) -> bool {
-        match input.node {
-            TreeF::Empty => false,
-            TreeF::Branch {..} => true,
+impl fmt::Display for DriverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DriverError::Usage(ref message) => write!(f, "{}", message),
+            DriverError::OpenInput(ref why) => write!(f, "could not open input file: {}", why),
+            DriverError::CreateOutput(ref why) => write!(f, "could not open output file: {}", why),
+            DriverError::CreateDot(ref why) => write!(f, "could not open DOT file: {}", why),
+            DriverError::Read(ref why) => write!(f, "error reading input: {}", why),
+            DriverError::Write(ref why) => write!(f, "error writing output: {}", why),
+            DriverError::Input { line, column, ref kind } => write!(f, "line {}, column {}: {}", line, column, kind),
         }
     }
 }
 
-// In order to simplify many of the tree operations, we define a zipper type,
-// which intuitively represents a location on the tree. To be precise, a zipper
-// consists of the following parts:
-// 1) A sequence of steps down the tree. Each step contains:
-//    a) a direction (left or right)
-//    b) the value in the parent node
-//    c) the sibling subtree
-// 2) The subtree below our location.
+impl Error for DriverError {}
 
-#[derive(Debug)]
-enum Direction {
-    Left,
-    Right,
-}
-
-#[derive(Debug)]
-struct TreeZipperStep<A, B> {
-    direction: Direction,
-    parent_val: A,
-    sibling: B,
+fn input_error(line: usize, error: ParseError) -> DriverError {
+    DriverError::Input {
+        line: line,
+        column: error.column,
+        kind: ErrorKind::Parse(error.kind),
+    }
 }
 
-#[derive(Debug)]
-struct TreeZipper<A, B> {
-    path: Vec<TreeZipperStep<A, B>>,
-    here: B,
+fn bit_char(bit: bool) -> char {
+    if bit { '1' } else { '0' }
 }
 
-fn find<A : Ord, B : TreeCoalgebra<A> + TreeAlgebra<A>>(root: B, v: &A) -> TreeZipper<A, B> {
-    let mut path = Vec::new();
-    let mut node = root;
-    loop {
-        match TreeCoalgebra::separate(node) {
-            TreeF::Empty => {
-                node = TreeAlgebra::combine(TreeF::Empty);
-                break;
-            },
-            TreeF::Branch { val, left, right } => {
-                match v.cmp(&val) {
-                    Ordering::Less => {
-                        path.push(TreeZipperStep {
-                            direction: Direction::Left,
-                            parent_val: val,
-                            sibling: right,
-                        });
-                        node = left;
-                    },
-                    Ordering::Equal => {
-                        node = TreeAlgebra::combine(TreeF::Branch { val: val, left: left, right: right });
-                        break;
-                    },
-                    Ordering::Greater => {
-                        path.push(TreeZipperStep {
-                            direction: Direction::Right,
-                            parent_val: val,
-                            sibling: left,
-                        });
-                        node = right;
-                    },
-                };
-            },
-        };
-    }
-    TreeZipper {
-        path: path,
-        here: node,
+fn read_line<R: BufRead>(input: &mut R, buf: &mut String, number: usize) -> Result<(), DriverError> {
+    buf.clear();
+    match input.read_line(buf) {
+        Err(why) => Err(DriverError::Read(why)),
+        Ok(0) => Err(DriverError::Input {
+            line: number,
+            column: 1,
+            kind: ErrorKind::UnexpectedEof,
+        }),
+        Ok(_) => Ok(()),
     }
 }
 
-fn zip_tree<A, B : TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> B {
-    let mut path = zipper.path;
-    let mut node = zipper.here;
-    while let Some(TreeZipperStep {direction, parent_val, sibling}) = path.pop() {
-        match direction {
-            Direction::Left => {
-                node = TreeAlgebra::combine(TreeF::Branch {
-                    val: parent_val,
-                    left: node,
-                    right: sibling,
-                });
-            },
-            Direction::Right => {
-                node = TreeAlgebra::combine(TreeF::Branch {
-                    val: parent_val,
-                    left: sibling,
-                    right: node,
-                });
-            },
-        }
-    }
-    node
-}
+#[derive(Debug)]
+struct Options {
+    unknown_commands: UnknownCommands,
+    input: Option<String>,
+    output: Option<String>,
+    dot: Option<String>,
+    dot_after: Option<String>,
+    stats: bool,
+}
+
+// Where to write DOT snapshots of the tree and which commands, by letter, to
+// take one after. No letters means after every command.
+struct Snapshots<'a> {
+    out: &'a mut dyn Write,
+    after: Option<&'a str>,
+}
+
+const USAGE: &str = "usage: rust_splay [--strict | --lenient] [--stats] [--dot FILE [--dot-after LETTERS]] [INPUT [OUTPUT]]
+       rust_splay repl [--set | --bits N] [--top-down | --bottom-up] [--policy POLICY] [--show-tree] [--top-down-view]
+Reads commands from INPUT and writes results to OUTPUT. Either defaults to
+standard input or output when omitted or given as `-`. The repl subcommand
+runs commands interactively; POLICY is full, semi, no-splay-on-read or depth=N.
+With --dot, a Graphviz snapshot of the tree is appended to FILE after every
+command, or only after the commands whose letters are given to --dot-after.
+With --stats, splay statistics are written to standard error at the end.";
 
-fn root_zipper<A, B>(root: B) -> TreeZipper<A, B> {
-    let path = Vec::new();
-    TreeZipper {
-        path: path,
-        here: root,
-    }
+#[derive(Debug)]
+enum Mode {
+    Batch(Options),
+    Repl(repl::ReplOptions),
 }
 
-fn parent_zipper<A, B: TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
-    let mut path = zipper.path;
-    match path.pop() {
-        None => {
-            TreeZipper {
-                path: path,
-                here: zipper.here,
-            }
+fn run(options: Options) -> Result<(), DriverError> {
+    let fin: Box<dyn BufRead> = match options.input {
+        Some(ref path) if path != "-" => {
+            Box::new(BufReader::new(File::open(path).map_err(DriverError::OpenInput)?))
         },
-        Some(TreeZipperStep {direction, parent_val, sibling}) => {
-            match direction {
-                Direction::Left => {
-                    TreeZipper {
-                        path: path,
-                        here: TreeAlgebra::combine(TreeF::Branch {
-                            val: parent_val,
-                            left: zipper.here,
-                            right: sibling,
-                        }),
-                    }
-                },
-                Direction::Right => {
-                    TreeZipper {
-                        path: path,
-                        here: TreeAlgebra::combine(TreeF::Branch {
-                            val: parent_val,
-                            left: sibling,
-                            right: zipper.here,
-                        }),
-                    }
-                },
-            }
+        _ => Box::new(BufReader::new(io::stdin())),
+    };
+    let fout: Box<dyn Write> = match options.output {
+        Some(ref path) if path != "-" => {
+            Box::new(BufWriter::new(File::create(path).map_err(DriverError::CreateOutput)?))
         },
+        _ => Box::new(BufWriter::new(io::stdout())),
+    };
+    let mut dot = match options.dot {
+        Some(ref path) => Some(BufWriter::new(File::create(path).map_err(DriverError::CreateDot)?)),
+        None => None,
+    };
+    let snapshots = dot.as_mut().map(|out| Snapshots {
+        out: out as &mut dyn Write,
+        after: options.dot_after.as_deref(),
+    });
+    let range = process(fin, fout, options.unknown_commands, snapshots, options.stats)?;
+    if let Some(stats) = range.stats() {
+        eprintln!("{}", stats);
     }
+    Ok(())
 }
 
-fn left_zipper<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
-    let mut path = zipper.path;
-    let mut node = zipper.here;
-    match TreeCoalgebra::separate(node) {
-        TreeF::Empty => {
-            node = TreeAlgebra::combine(TreeF::Empty);
-        },
-        TreeF::Branch{val, left, right} => {
-            path.push(TreeZipperStep {
-                direction: Direction::Left,
-                parent_val: val,
-                sibling: right,
-            });
-            node = left;
-        },
-    }
-    TreeZipper {
-        path: path,
-        here: node,
-    }
+// Errors from running a single parsed command.
+#[derive(Debug)]
+enum ExecError {
+    OutOfRange(usize),
+    Write(io::Error),
 }
 
-fn right_zipper<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
-    let mut path = zipper.path;
-    let mut node = zipper.here;
-    match TreeCoalgebra::separate(node) {
-        TreeF::Empty => {
-            node = TreeAlgebra::combine(TreeF::Empty);
+fn execute<W: Write>(range: &mut BitRange, command: Command, fout: &mut W) -> Result<(), ExecError> {
+    match command {
+        Command::Set(idx, val) => range.try_set(idx, val).map_err(|_| ExecError::OutOfRange(idx))?,
+        Command::Get(idx) => {
+            let val = range.get(idx).ok_or(ExecError::OutOfRange(idx))?;
+            writeln!(fout, "{}", bit_char(val)).map_err(ExecError::Write)?;
         },
-        TreeF::Branch{val, left, right} => {
-            path.push(TreeZipperStep {
-                direction: Direction::Right,
-                parent_val: val,
-                sibling: left,
-            });
-            node = right;
+        Command::Reverse(start, end) => range.reverse_range(start, end),
+        Command::Insert(idx, val) => range.try_insert(idx, val).map_err(|_| ExecError::OutOfRange(idx))?,
+        Command::Delete(idx) => {
+            range.remove(idx).ok_or(ExecError::OutOfRange(idx))?;
         },
-    }
-    TreeZipper {
-        path: path,
-        here: node,
-    }
-}
-
-fn rotate_zipper<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
-    let mut path = zipper.path;
-    let mut node = zipper.here;
-    match TreeCoalgebra::separate(node) {
-        TreeF::Empty => {
-            node = TreeAlgebra::combine(TreeF::Empty);
+        Command::Count(start, end) => {
+            writeln!(fout, "{}", range.count_ones(start, end)).map_err(ExecError::Write)?;
+        },
+        Command::Flip(start, end) => range.flip_range(start, end),
+        Command::Fill(start, end, val) => range.fill_range(start, end, val),
+        Command::Print => {
+            let bits: String = range.to_vec().into_iter().map(bit_char).collect();
+            writeln!(fout, "{}", bits).map_err(ExecError::Write)?;
         },
-        TreeF::Branch {val, left, right} => {
-            if let Some(TreeZipperStep {direction, parent_val, sibling}) = path.pop() {
-                match direction {
-                    Direction::Left => {
-                        node = TreeAlgebra::combine(TreeF::Branch {
-                            val: val,
-                            left: left,
-                            right: TreeAlgebra::combine(TreeF::Branch {
-                                val: parent_val,
-                                left: right,
-                                right: sibling,
-                            }),
-                        });
-                    },
-                    Direction::Right => {
-                        node = TreeAlgebra::combine(TreeF::Branch {
-                            val: val,
-                            left: TreeAlgebra::combine(TreeF::Branch {
-                                val: parent_val,
-                                left: sibling,
-                                right: left,
-                            }),
-                            right: right,
-                        });
-                    },
+        Command::Move(start, end, to) => range.move_range(start, end, to),
+    }
+    Ok(())
+}
+
+fn process<R: BufRead, W: Write>(mut fin: R, mut fout: W, unknown_commands: UnknownCommands,
+                                 mut snapshots: Option<Snapshots>, stats: bool) -> Result<BitRange, DriverError> {
+    let mut buf = String::new();
+    read_line(&mut fin, &mut buf, 1)?;
+    let header = parse_header(&buf).map_err(|error| input_error(1, error))?;
+    let mut range = BitRange::new(header.length);
+    if stats {
+        range.enable_stats();
+    }
+    for i in 0..header.commands {
+        let number = i + 2;
+        read_line(&mut fin, &mut buf, number)?;
+        let command = match parse_command(&buf) {
+            Ok(command) => command,
+            Err(error) => {
+                let unknown = matches!(error.kind, ParseErrorKind::UnknownCommand(_));
+                let error = input_error(number, error);
+                if !unknown || unknown_commands == UnknownCommands::Reject {
+                    return Err(error);
                 }
-            } else {
-                node = TreeAlgebra::combine(TreeF::Branch{
-                    val: val,
-                    left: left,
-                    right: right,
+                eprintln!("warning: {}", error);
+                continue;
+            },
+        };
+        match execute(&mut range, command, &mut fout) {
+            Ok(()) => {},
+            Err(ExecError::Write(why)) => return Err(DriverError::Write(why)),
+            Err(ExecError::OutOfRange(index)) => {
+                return Err(DriverError::Input {
+                    line: number,
+                    column: argument_column(&buf, 0),
+                    kind: ErrorKind::IndexOutOfRange(index),
                 });
-            }
-        },
-    }
-    TreeZipper {
-        path: path,
-        here: node,
-    }
-}
-
-fn splay<A, B: TreeAlgebra<A> + TreeCoalgebra<A>>(mut zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
-    if zipper.path.is_empty() {
-        return zipper
-    }
-    if !TreeCoalgebra::is_branch(&zipper.here) {
-        zipper = parent_zipper(zipper)
-    }
-    while !zipper.path.is_empty() {
-        zipper = splay_step(zipper);
-    }
-    zipper
-}
-
-fn splay_step<A, B: TreeAlgebra<A> + TreeCoalgebra<A>>(zipper: TreeZipper<A, B>) -> TreeZipper<A, B> {
-    let mut path = zipper.path;
-    match TreeCoalgebra::separate(zipper.here) {
-        TreeF::Empty => TreeZipper{path: path, here: TreeAlgebra::combine(TreeF::Empty)},
-        TreeF::Branch {val, left, right} => {
-            match path.pop() {
-                None => {
-                    TreeZipper{path: path, here: TreeAlgebra::combine(TreeF::Branch{val: val, left: left, right: right})}
-                },
-                Some(TreeZipperStep {direction, parent_val, sibling}) => {
-                    match path.pop() {
-                        None => {
-                            match direction {
-                                Direction::Left => {
-                                    TreeZipper {
-                                        path: path,
-                                        here: TreeAlgebra::combine(TreeF::Branch {
-                                            val: val,
-                                            left: left,
-                                            right: TreeAlgebra::combine(TreeF::Branch {
-                                                val: parent_val,
-                                                left: right,
-                                                right: sibling,
-                                            }),
-                                        }),
-                                    }
-                                },
-                                Direction::Right => {
-                                    TreeZipper {
-                                        path: path,
-                                        here: TreeAlgebra::combine(TreeF::Branch {
-                                            val: val,
-                                            left: TreeAlgebra::combine(TreeF::Branch {
-                                                val: parent_val,
-                                                left: sibling,
-                                                right: left,
-                                            }),
-                                            right: right,
-                                        }),
-                                    }
-                                },
-                            }
-                        },
-                        Some(TreeZipperStep {direction: parent_dir, parent_val: grandparent_val, sibling: uncle}) => {
-                            match (direction, parent_dir) {
-                                (Direction::Left, Direction::Left) => {
-                                    TreeZipper {
-                                        path: path,
-                                        here: TreeAlgebra::combine(TreeF::Branch {
-                                            val: val,
-                                            left: left,
-                                            right: TreeAlgebra::combine(TreeF::Branch {
-                                                val: parent_val,
-                                                left: right,
-                                                right: TreeAlgebra::combine(TreeF::Branch {
-                                                    val: grandparent_val,
-                                                    left: sibling,
-                                                    right: uncle,
-                                                }),
-                                            }),
-                                        }),
-                                    }
-                                },
-                                (Direction::Left, Direction::Right) => {
-                                    TreeZipper {
-                                        path: path,
-                                        here: TreeAlgebra::combine(TreeF::Branch {
-                                            val: val,
-                                            left: TreeAlgebra::combine(TreeF::Branch {
-                                                val: grandparent_val,
-                                                left: uncle,
-                                                right: left,
-                                            }),
-                                            right: TreeAlgebra::combine(TreeF::Branch {
-                                                val: parent_val,
-                                                left: right,
-                                                right: sibling,
-                                            }),
-                                        }),
-                                    }
-                                },
-                                (Direction::Right, Direction::Left) => {
-                                    TreeZipper {
-                                        path: path,
-                                        here: TreeAlgebra::combine(TreeF::Branch {
-                                            val: val,
-                                            left: TreeAlgebra::combine(TreeF::Branch {
-                                                val: parent_val,
-                                                left: sibling,
-                                                right: left,
-                                            }),
-                                            right: TreeAlgebra::combine(TreeF::Branch {
-                                                val: grandparent_val,
-                                                left: right,
-                                                right: uncle,
-                                            }),
-                                        }),
-                                    }
-                                },
-                                (Direction::Right, Direction::Right) => {
-                                    TreeZipper {
-                                        path: path,
-                                        here: TreeAlgebra::combine(TreeF::Branch {
-                                            val: val,
-                                            left: TreeAlgebra::combine(TreeF::Branch {
-                                                val: parent_val,
-                                                left: TreeAlgebra::combine(TreeF::Branch {
-                                                    val: grandparent_val,
-                                                    left: uncle,
-                                                    right: sibling,
-                                                }),
-                                                right: left,
-                                            }),
-                                            right: right,
-                                        }),
-                                    }
-                                },
-                            }
-                        },
-                    }
-                },
-            }
-        },
-    }
-}
-
-#[derive(Debug)]
-struct SplayTree<A> {
-    root: TreeNode<A>,
-}
-
-trait Splay<A> {
-    fn new() -> Self;
-    fn insert(&mut self, A);
-    fn contains(&mut self, A) -> bool;
-    fn splay_to_root(&mut self, A);
-}
-
-impl<A: Ord> Splay<A> for SplayTree<A> {
-    fn new() -> SplayTree<A> {
-        SplayTree {
-            root: TreeNode(TreeF::Empty),
+            },
         }
-    }
-
-    fn insert(&mut self, v: A) {
-        let old_root = mem::replace(&mut self.root, TreeNode(TreeF::Empty));
-        let mut ins_loc = find(old_root, &v);
-        if let TreeNode(TreeF::Empty) = ins_loc.here {
-            ins_loc.here = TreeNode(TreeF::Branch {
-                val: v,
-                left: Box::new(TreeNode(TreeF::Empty)),
-                right: Box::new(TreeNode(TreeF::Empty)),
-            });
+        if let Some(ref mut snapshots) = snapshots {
+            if snapshots.after.is_none_or(|letters| letters.contains(command.letter())) {
+                write!(snapshots.out, "// after line {}: {}\n{}", number, command, range.root().to_dot())
+                    .map_err(DriverError::Write)?;
+            }
         }
-        self.root = zip_tree(splay(ins_loc));
-    }
-
-    fn contains(&mut self, v: A) -> bool {
-        let old_root = mem::replace(&mut self.root, TreeNode(TreeF::Empty));
-        let find_loc = find(old_root, &v);
-        let result = match find_loc.here {
-            TreeNode(TreeF::Empty) => false,
-            TreeNode(TreeF::Branch { .. }) => true,
-        };
-        self.root = zip_tree(splay(find_loc));
-        result
     }
-
-    fn splay_to_root(&mut self, v: A) {
-        let old_root = mem::replace(&mut self.root, TreeNode(TreeF::Empty));
-        self.root = zip_tree(splay(find(old_root, &v)));
+    if let Some(snapshots) = snapshots {
+        snapshots.out.flush().map_err(DriverError::Write)?;
     }
+    fout.flush().map_err(DriverError::Write)?;
+    Ok(range)
 }
 
-#[derive(Debug)]
-enum BitRangeNode {
-    Empty,
-    Branch {
-        here: bool,
-        size: i32,
-        reversed: bool,
-        left: Box<BitRangeNode>,
-        right: Box<BitRangeNode>,
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Mode, DriverError> {
+    let mut args = args.peekable();
+    if args.peek().map(|arg| arg.as_str()) == Some("repl") {
+        args.next();
+        return repl::parse_args(args).map(Mode::Repl);
     }
+    parse_batch_args(args).map(Mode::Batch)
 }
 
-trait Reversible {
-    fn reversed(Self) -> Self;
-}
-
-impl Reversible for BitRangeNode {
-    fn reversed(input: BitRangeNode) -> BitRangeNode {
-        match input {
-            BitRangeNode::Empty => {
-                BitRangeNode::Empty
-            },
-            BitRangeNode::Branch { here, size, reversed, left, right } => {
-                BitRangeNode::Branch {
-                    here: here,
-                    size: size,
-                    reversed: !reversed,
-                    left: left,
-                    right: right,
+fn parse_batch_args<I: Iterator<Item = String>>(args: I) -> Result<Options, DriverError> {
+    let mut options = Options {
+        unknown_commands: UnknownCommands::Reject,
+        input: None,
+        output: None,
+        dot: None,
+        dot_after: None,
+        stats: false,
+    };
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--strict" => options.unknown_commands = UnknownCommands::Reject,
+            "--lenient" => options.unknown_commands = UnknownCommands::Skip,
+            "--stats" => options.stats = true,
+            "--dot" | "--dot-after" => {
+                let value = args.next()
+                    .ok_or_else(|| DriverError::Usage(format!("missing value for `{}`\n{}", arg, USAGE)))?;
+                if arg == "--dot" {
+                    options.dot = Some(value);
+                } else if let Some(c) = value.chars().find(|&c| !COMMAND_LETTERS.contains(c)) {
+                    return Err(DriverError::Usage(format!("unknown command letter `{}`\n{}", c, USAGE)));
+                } else {
+                    options.dot_after = Some(value);
                 }
             },
-        }
-    }
-}
-
-fn get_size(n: &BitRangeNode) -> i32 {
-    match *n {
-        BitRangeNode::Empty => 0,
-        BitRangeNode::Branch { size: s, .. } => s,
-    }
-}
-
-impl TreeAlgebra<bool> for BitRangeNode {
-    fn combine(input: TreeF<bool, BitRangeNode>) -> BitRangeNode {
-        match input {
-            TreeF::Empty => {
-                BitRangeNode::Empty
+            _ if arg.starts_with("--") => {
+                return Err(DriverError::Usage(format!("unrecognized option `{}`\n{}", arg, USAGE)));
             },
-            TreeF::Branch { val, left, right } => {
-                BitRangeNode::Branch {
-                    here: val,
-                    size: get_size(&left) + get_size(&right) + 1,
-                    reversed: false,
-                    left: Box::new(left),
-                    right: Box::new(right),
-                }
-            }
+            _ if options.input.is_none() => options.input = Some(arg),
+            _ if options.output.is_none() => options.output = Some(arg),
+            _ => return Err(DriverError::Usage(format!("unexpected argument `{}`\n{}", arg, USAGE))),
         }
     }
+    Ok(options)
 }
 
-impl TreeCoalgebra<bool> for BitRangeNode {
-    fn separate(input: BitRangeNode) -> TreeF<bool, BitRangeNode> {
-        match input {
-            BitRangeNode::Empty => {
-                TreeF::Empty
-            },
-            BitRangeNode::Branch {here, reversed, left, right, ..} => {
-                if reversed {
-                    TreeF::Branch {
-                        val: here,
-                        left: Reversible::reversed(*right),
-                        right: Reversible::reversed(*left),
-                    }
-                } else {
-                    TreeF::Branch {
-                        val: here,
-                        left: *left,
-                        right: *right,
-                    }
-                }
-            },
-        }
-    }
-    fn is_branch(input: &BitRangeNode) -> bool {
-        match *input {
-            BitRangeNode::Empty => false,
-            BitRangeNode::Branch {..} => true,
+fn main() {
+    let result = parse_args(env::args().skip(1)).and_then(|mode| {
+        match mode {
+            Mode::Batch(options) => run(options),
+            Mode::Repl(options) => repl::run(options),
         }
+    });
+    if let Err(why) = result {
+        eprintln!("error: {}", why);
+        process::exit(match why {
+            DriverError::Usage(_) => 2,
+            _ => 1,
+        });
     }
 }
 
-fn end<A, B: TreeCoalgebra<A> + TreeAlgebra<A>>(root: B) -> TreeZipper<A, B> {
-    let mut node = root;
-    let mut path = Vec::new();
-    loop {
-        match TreeCoalgebra::separate(node) {
-            TreeF::Empty => {
-                node = TreeAlgebra::combine(TreeF::Empty);
-                break;
-            },
-            TreeF::Branch {val, left, right} => {
-                path.push(TreeZipperStep {
-                    direction: Direction::Right,
-                    parent_val: val,
-                    sibling: left,
-                });
-                node = right;
-            }
-        }
-    }
-    TreeZipper {
-        here: node,
-        path: path,
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn find_index(root: BitRangeNode, index: i32) -> TreeZipper<bool, BitRangeNode> {
-    let mut node = root;
-    let mut remaining = index;
-    let mut path = Vec::new();
-    loop {
-        match TreeCoalgebra::separate(node) {
-            TreeF::Empty => {
-                node = TreeAlgebra::combine(TreeF::Empty);
-                break;
-            },
-            TreeF::Branch { val, left, right } => {
-                let left_size = get_size(&left);
-                match left_size.cmp(&remaining) {
-                    Ordering::Less => {
-                        path.push(TreeZipperStep {
-                            direction: Direction::Right,
-                            parent_val: val,
-                            sibling: left,
-                        });
-                        node = right;
-                        remaining = remaining - left_size - 1;
-                    },
-                    Ordering::Equal => {
-                        node = TreeAlgebra::combine(TreeF::Branch {val: val, left: left, right: right});
-                        break;
-                    },
-                    Ordering::Greater => {
-                        path.push(TreeZipperStep {
-                            direction: Direction::Left,
-                            parent_val: val,
-                            sibling: right,
-                        });
-                        node = left;
-                    },
-                }
-            },
+    fn error_position(error: DriverError) -> (usize, usize) {
+        match error {
+            DriverError::Input { line, column, .. } => (line, column),
+            other => panic!("unexpected error {}", other),
         }
     }
-    TreeZipper {
-        path: path,
-        here: node,
-    }
-}
 
-fn isolate_interval(root: BitRangeNode, index_start: i32, index_end: i32) -> TreeZipper<bool, BitRangeNode> {
-    let mut cur_root = root;
-    if index_start <= 0 {
-        if index_end >= get_size(&cur_root) {
-            root_zipper(cur_root)
-        } else {
-            left_zipper(splay(find_index(cur_root, index_end)))
-        }
-    } else {
-        if index_end >= get_size(&cur_root) {
-            right_zipper(splay(find_index(cur_root, index_start - 1)))
-        } else {
-            cur_root = zip_tree(splay(find_index(cur_root, index_start)));
-            cur_root = zip_tree(splay(find_index(cur_root, index_start-1)));
-            cur_root = zip_tree(splay(find_index(cur_root, index_end)));
-            let zipper = right_zipper(find_index(cur_root, index_start-1));
-            if TreeCoalgebra::is_branch(&zipper.here) {
-                zipper
-            } else {
-                right_zipper(rotate_zipper(parent_zipper(zipper)))
-            }
-        }
+    fn process_str(input: &str, unknown_commands: UnknownCommands) -> Result<String, DriverError> {
+        let mut output = Vec::new();
+        process(input.as_bytes(), &mut output, unknown_commands, None, false)?;
+        Ok(String::from_utf8(output).unwrap())
     }
-}
 
-#[derive(Debug)]
-struct BitRange {
-    root: BitRangeNode,
-}
-
-impl BitRange {
-    fn new(n: i32) -> BitRange {
-        let mut root = BitRangeNode::Empty;
-        for _ in 0..n {
-            let mut zipper = end(root);
-            zipper.here = TreeAlgebra::combine(TreeF::Branch {
-                val: false,
-                left: BitRangeNode::Empty,
-                right: BitRangeNode::Empty,
-            });
-            root = zip_tree(splay(zipper));
-        }
-        BitRange {
-            root: root,
+    fn args(args: &[&str]) -> Result<Options, DriverError> {
+        match parse_args(args.iter().map(|arg| arg.to_string()))? {
+            Mode::Batch(options) => Ok(options),
+            Mode::Repl(options) => panic!("unexpected repl options {:?}", options),
         }
     }
 
-    fn set(self: &mut BitRange, index: i32, val: bool) {
-        let old_root = mem::replace(&mut self.root, BitRangeNode::Empty);
-        let mut zipper = find_index(old_root, index);
-        zipper.here = match zipper.here {
-            BitRangeNode::Empty => {
-                BitRangeNode::Empty
-            },
-            BitRangeNode::Branch { size, reversed, left, right, .. } => {
-                BitRangeNode::Branch {
-                    here: val,
-                    size: size,
-                    reversed: reversed,
-                    left: left,
-                    right: right,
-                }
-            },
-        };
-        self.root = zip_tree(splay(zipper));
+    #[test]
+    fn processes_commands_from_a_stream() {
+        let output = process_str("5 6\nS 1 1\nG 1\nR 0 3\nG 1\nG 2\nG 4\n", UnknownCommands::Reject);
+        assert_eq!(output.unwrap(), "1\n1\n0\n0\n");
     }
 
-    fn get(self: &mut BitRange, index: i32) -> Option<bool> {
-        let old_root = mem::replace(&mut self.root, BitRangeNode::Empty);
-        let zipper = find_index(old_root, index);
-        let result = match zipper.here {
-            BitRangeNode::Empty => None,
-            BitRangeNode::Branch { here, .. } => Some(here),
-        };
-        self.root = zip_tree(splay(zipper));
-        result
+    #[test]
+    fn unknown_commands_depend_on_mode() {
+        let input = "3 2\nX 1\nG 0\n";
+        assert_eq!(error_position(process_str(input, UnknownCommands::Reject).unwrap_err()), (2, 1));
+        assert_eq!(process_str(input, UnknownCommands::Skip).unwrap(), "0\n");
     }
 
-    fn reverse_range(self: &mut BitRange, index_start: i32, index_end: i32) {
-        let tmp_root = mem::replace(&mut self.root, BitRangeNode::Empty);
-        let mut zipper = isolate_interval(tmp_root, index_start, index_end);
-        zipper.here = Reversible::reversed(zipper.here);
-        self.root = zip_tree(zipper);
+    #[test]
+    fn parses_paths_and_modes() {
+        let options = args(&["--lenient", "in.txt", "-"]).unwrap();
+        assert_eq!(options.unknown_commands, UnknownCommands::Skip);
+        assert_eq!(options.input, Some("in.txt".to_string()));
+        assert_eq!(options.output, Some("-".to_string()));
+        let options = args(&[]).unwrap();
+        assert_eq!(options.unknown_commands, UnknownCommands::Reject);
+        assert_eq!(options.input, None);
+        assert!(args(&["a", "b", "c"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+        let mode = parse_args(vec!["repl".to_string(), "--show-tree".to_string()].into_iter()).unwrap();
+        assert!(matches!(mode, Mode::Repl(ref options) if options.show_tree));
     }
-}
 
-fn main() {
-    let fin = match File::open("range_reverse.in") {
-        Err(why) => panic!("Could not open input file: {}", why.description()),
-        Ok(file) => file,
-    };
-    let mut fin = BufReader::new(fin);
-    let fout = match File::create("range_reverse.out") {
-        Err(why) => panic!("Could not open output file: {}", why.description()),
-        Ok(file) => file,
-    };
-    let mut fout = BufWriter::new(fout);
+    #[test]
+    fn extended_commands() {
+        let input = "4 9\nA 1 3 1\nP\nI 0 1\nC 0 5\nM 0 2 3\nP\nF 0 5\nD 4\nP\n";
+        assert_eq!(process_str(input, UnknownCommands::Reject).unwrap(), "0110\n3\n11010\n0010\n");
+    }
 
-    let mut line1 = String::new();
-    match fin.read_line(&mut line1) {
-        Err(why) => panic!("Error reading data: {}", why.description()),
-        Ok(_) => {},
-    };
-    let line1_tokens : Vec<&str> = line1.trim_right().split(' ').collect();
-    let n = match line1_tokens[0].parse::<i32>() {
-        Err(why) => panic!("Error parsing data: {}", why.description()),
-        Ok(n) => n,
-    };
-    let m = match line1_tokens[1].parse::<i32>() {
-        Err(why) => panic!("Error parsing data: {}", why.description()),
-        Ok(n) => n,
-    };
-    let mut range = BitRange::new(n);
-    for _ in 0..m {
-        let mut line = String::new();
-        match fin.read_line(&mut line) {
-            Err(why) => panic!("Error reading data: {}", why.description()),
-            Ok(_) => {},
+    #[test]
+    fn snapshots_follow_chosen_commands() {
+        let mut dot = Vec::new();
+        let mut output = Vec::new();
+        let snapshots = Snapshots {
+            out: &mut dot,
+            after: Some("RF"),
         };
-        let tokens : Vec<&str> = line.trim_right().split(' ').collect();
-        if tokens[0] == "S" {
-            let idx = match tokens[1].parse::<i32>() {
-                Err(why) => panic!("Error parsing data: {}", why.description()),
-                Ok(n) => n,
-            };
-            let val = tokens[2] == "1";
-            range.set(idx, val);
-        } else if tokens[0] == "G" {
-            let idx = match tokens[1].parse::<i32>() {
-                Err(why) => panic!("Error parsing data: {}", why.description()),
-                Ok(n) => n,
-            };
-            let val = match range.get(idx) {
-                None => panic!("Requested index out of range!"),
-                Some(b) => b,
-            };
-            write!(fout, "{}\n", if val { 1 } else { 0 }).unwrap();
-        } else if tokens[0] == "R" {
-            let idx1 = match tokens[1].parse::<i32>() {
-                Err(why) => panic!("Error parsing data: {}", why.description()),
-                Ok(n) => n,
-            };
-            let idx2 = match tokens[2].parse::<i32>() {
-                Err(why) => panic!("Error parsing data: {}", why.description()),
-                Ok(n) => n,
-            };
-            range.reverse_range(idx1, idx2);
-        }
+        process("2 3\nS 0 1\nR 0 2\nG 0\n".as_bytes(), &mut output, UnknownCommands::Reject, Some(snapshots), false).unwrap();
+        let dot = String::from_utf8(dot).unwrap();
+        assert!(dot.starts_with("// after line 3: R 0 2\ndigraph tree {\n"), "{}", dot);
+        assert_eq!(dot.matches("digraph").count(), 1);
+        let options = args(&["--dot", "out.dot", "--dot-after", "RM"]).unwrap();
+        assert_eq!(options.dot, Some("out.dot".to_string()));
+        assert_eq!(options.dot_after, Some("RM".to_string()));
+        assert!(args(&["--dot-after", "RX"]).is_err());
+        assert!(args(&["--dot"]).is_err());
+    }
+
+    #[test]
+    fn collects_stats_on_request() {
+        let mut output = Vec::new();
+        let range = process("4 2\nG 3\nG 0\n".as_bytes(), &mut output, UnknownCommands::Reject, None, true).unwrap();
+        assert_eq!(range.stats().unwrap().operations, 2);
+        assert!(args(&["--stats"]).unwrap().stats);
+    }
+
+    #[test]
+    fn index_errors_point_at_the_index() {
+        let input = "3 2\nI 3 1\nD  4\n";
+        assert_eq!(error_position(process_str(input, UnknownCommands::Reject).unwrap_err()), (3, 4));
+        let input = "3 1\nG 3\n";
+        assert_eq!(error_position(process_str(input, UnknownCommands::Reject).unwrap_err()), (2, 3));
+        let input = "3 2\nS 2 1\nS 3 1\n";
+        assert_eq!(error_position(process_str(input, UnknownCommands::Reject).unwrap_err()), (3, 3));
+    }
+
+    #[test]
+    fn parse_errors_are_not_skipped() {
+        let input = "3 1\nS x 1\n";
+        assert_eq!(error_position(process_str(input, UnknownCommands::Skip).unwrap_err()), (2, 3));
+        let input = "3 -1\n";
+        assert_eq!(error_position(process_str(input, UnknownCommands::Reject).unwrap_err()), (1, 3));
     }
 }
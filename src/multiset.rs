@@ -0,0 +1,319 @@
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+use std::mem;
+
+use {TreeF, TreeAlgebra, TreeCoalgebra, SplayStrategy};
+use {join, splay_by};
+use stats;
+
+// A splay tree that keeps duplicates. Each node holds a distinct value with
+// the number of times it occurs, and the total number of occurrences in its
+// subtree, so ranks and selection count every copy. Values are compared only
+// with `Ord`; equal values are interchangeable and one of them is kept.
+
+#[derive(Debug)]
+pub enum MultisetNode<A> {
+    Empty,
+    Branch {
+        val: A,
+        count: usize,
+        total: usize,
+        left: Box<MultisetNode<A>>,
+        right: Box<MultisetNode<A>>,
+    },
+}
+
+impl<A> TreeAlgebra<(A, usize)> for MultisetNode<A> {
+    fn combine(input: TreeF<(A, usize), MultisetNode<A>>) -> MultisetNode<A> {
+        match input {
+            TreeF::Empty => {
+                MultisetNode::Empty
+            },
+            TreeF::Branch { val: (val, count), left, right } => {
+                stats::record(|stats| stats.allocations += 2);
+                MultisetNode::Branch {
+                    val: val,
+                    count: count,
+                    total: get_total(&left) + get_total(&right) + count,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            },
+        }
+    }
+}
+
+impl<A> TreeCoalgebra<(A, usize)> for MultisetNode<A> {
+    fn separate(input: MultisetNode<A>) -> TreeF<(A, usize), MultisetNode<A>> {
+        match input {
+            MultisetNode::Empty => {
+                TreeF::Empty
+            },
+            MultisetNode::Branch { val, count, left, right, .. } => {
+                TreeF::Branch {
+                    val: (val, count),
+                    left: *left,
+                    right: *right,
+                }
+            },
+        }
+    }
+    fn is_branch(input: &MultisetNode<A>) -> bool {
+        match *input {
+            MultisetNode::Empty => false,
+            MultisetNode::Branch {..} => true,
+        }
+    }
+}
+
+fn get_total<A>(n: &MultisetNode<A>) -> usize {
+    match *n {
+        MultisetNode::Empty => 0,
+        MultisetNode::Branch { total, .. } => total,
+    }
+}
+
+#[derive(Debug)]
+pub struct SplayMultiset<A> {
+    root: MultisetNode<A>,
+    strategy: SplayStrategy,
+}
+
+impl<A: Ord> SplayMultiset<A> {
+    pub fn new() -> SplayMultiset<A> {
+        SplayMultiset::with_strategy(SplayStrategy::BottomUp)
+    }
+
+    pub fn with_strategy(strategy: SplayStrategy) -> SplayMultiset<A> {
+        SplayMultiset {
+            root: MultisetNode::Empty,
+            strategy: strategy,
+        }
+    }
+
+    pub fn root(&self) -> &MultisetNode<A> {
+        &self.root
+    }
+
+    // The number of elements, counting every copy.
+    pub fn len(&self) -> usize {
+        get_total(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Splays `v`, or a neighbour of it if it is absent, to the root.
+    fn splay_key(&mut self, v: &A) {
+        let old_root = mem::replace(&mut self.root, MultisetNode::Empty);
+        self.root = splay_by(old_root, |entry: &(A, usize), _| v.cmp(&entry.0), self.strategy);
+    }
+
+    // Adds one copy of `v` and returns how many there are now.
+    pub fn insert(&mut self, v: A) -> usize {
+        self.splay_key(&v);
+        let (val, count, left, right) = match TreeCoalgebra::separate(mem::replace(&mut self.root, MultisetNode::Empty)) {
+            TreeF::Empty => (v, 1, MultisetNode::Empty, MultisetNode::Empty),
+            TreeF::Branch { val: (val, count), left, right } => {
+                match v.cmp(&val) {
+                    Ordering::Less => (v, 1, left, TreeAlgebra::combine(TreeF::Branch {
+                        val: (val, count),
+                        left: MultisetNode::Empty,
+                        right: right,
+                    })),
+                    Ordering::Equal => (val, count + 1, left, right),
+                    Ordering::Greater => (v, 1, TreeAlgebra::combine(TreeF::Branch {
+                        val: (val, count),
+                        left: left,
+                        right: MultisetNode::Empty,
+                    }), right),
+                }
+            },
+        };
+        self.root = TreeAlgebra::combine(TreeF::Branch {
+            val: (val, count),
+            left: left,
+            right: right,
+        });
+        count
+    }
+
+    pub fn count(&mut self, v: &A) -> usize {
+        self.splay_key(v);
+        match self.root {
+            MultisetNode::Branch { ref val, count, .. } if val == v => count,
+            _ => 0,
+        }
+    }
+
+    pub fn contains(&mut self, v: &A) -> bool {
+        self.count(v) > 0
+    }
+
+    // Removes up to `n` copies of `v`, returning how many were removed.
+    fn remove_up_to(&mut self, v: &A, n: usize) -> usize {
+        if self.count(v) == 0 {
+            return 0;
+        }
+        match TreeCoalgebra::separate(mem::replace(&mut self.root, MultisetNode::Empty)) {
+            TreeF::Empty => 0,
+            TreeF::Branch { val: (val, count), left, right } => {
+                if count > n {
+                    self.root = TreeAlgebra::combine(TreeF::Branch {
+                        val: (val, count - n),
+                        left: left,
+                        right: right,
+                    });
+                    n
+                } else {
+                    self.root = join(left, right);
+                    count
+                }
+            },
+        }
+    }
+
+    // Removes one copy of `v`, returning whether there was one.
+    pub fn remove_one(&mut self, v: &A) -> bool {
+        self.remove_up_to(v, 1) == 1
+    }
+
+    // Removes every copy of `v`, returning how many there were.
+    pub fn remove_all(&mut self, v: &A) -> usize {
+        self.remove_up_to(v, usize::MAX)
+    }
+
+    // The number of elements strictly less than `v`.
+    pub fn rank(&mut self, v: &A) -> usize {
+        self.splay_key(v);
+        match self.root {
+            MultisetNode::Empty => 0,
+            MultisetNode::Branch { ref val, count, ref left, .. } => {
+                if *val < *v {
+                    get_total(left) + count
+                } else {
+                    get_total(left)
+                }
+            },
+        }
+    }
+
+    // The element at position `index` when all of them are listed in order,
+    // so a value with several copies fills several consecutive positions.
+    pub fn select(&mut self, index: usize) -> Option<&A> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut remaining = index;
+        let old_root = mem::replace(&mut self.root, MultisetNode::Empty);
+        self.root = splay_by(old_root, |&(_, count), left| {
+            let left_total = get_total(left);
+            if remaining < left_total {
+                Ordering::Less
+            } else if remaining < left_total + count {
+                Ordering::Equal
+            } else {
+                remaining -= left_total + count;
+                Ordering::Greater
+            }
+        }, self.strategy);
+        match self.root {
+            MultisetNode::Empty => None,
+            MultisetNode::Branch { ref val, .. } => Some(val),
+        }
+    }
+
+    // Distinct values in order, with their counts.
+    pub fn iter(&self) -> Iter<'_, A> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+        };
+        iter.descend(&self.root);
+        iter
+    }
+}
+
+impl<A: Ord> Default for SplayMultiset<A> {
+    fn default() -> SplayMultiset<A> {
+        SplayMultiset::new()
+    }
+}
+
+impl<A: Ord> Extend<A> for SplayMultiset<A> {
+    fn extend<I: IntoIterator<Item = A>>(&mut self, iter: I) {
+        for v in iter {
+            self.insert(v);
+        }
+    }
+}
+
+impl<A: Ord> FromIterator<A> for SplayMultiset<A> {
+    fn from_iter<I: IntoIterator<Item = A>>(iter: I) -> SplayMultiset<A> {
+        let mut multiset = SplayMultiset::new();
+        multiset.extend(iter);
+        multiset
+    }
+}
+
+pub struct Iter<'a, A: 'a> {
+    stack: Vec<&'a MultisetNode<A>>,
+}
+
+impl<'a, A> Iter<'a, A> {
+    fn descend(&mut self, node: &'a MultisetNode<A>) {
+        let mut node = node;
+        while let MultisetNode::Branch { ref left, .. } = *node {
+            self.stack.push(node);
+            node = left;
+        }
+    }
+}
+
+impl<'a, A> Iterator for Iter<'a, A> {
+    type Item = (&'a A, usize);
+
+    fn next(&mut self) -> Option<(&'a A, usize)> {
+        match self.stack.pop() {
+            Some(&MultisetNode::Branch { ref val, count, ref right, .. }) => {
+                self.descend(right);
+                Some((val, count))
+            },
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_duplicates() {
+        let mut multiset: SplayMultiset<i32> = vec![5, 3, 5, 8, 5, 3].into_iter().collect();
+        assert_eq!(multiset.len(), 6);
+        assert_eq!((multiset.count(&5), multiset.count(&3), multiset.count(&4)), (3, 2, 0));
+        assert_eq!(multiset.insert(8), 2);
+        assert_eq!(multiset.iter().collect::<Vec<_>>(), vec![(&3, 2), (&5, 3), (&8, 2)]);
+        assert!(multiset.remove_one(&5));
+        assert!(!multiset.remove_one(&4));
+        assert_eq!(multiset.remove_all(&3), 2);
+        assert_eq!(multiset.remove_all(&3), 0);
+        assert_eq!(multiset.len(), 4);
+        assert!(!multiset.contains(&3) && multiset.contains(&5));
+    }
+
+    #[test]
+    fn rank_and_select_count_copies() {
+        for &strategy in &[SplayStrategy::BottomUp, SplayStrategy::TopDown] {
+            let mut multiset = SplayMultiset::with_strategy(strategy);
+            multiset.extend(vec![2, 7, 2, 4, 7, 7]);
+            let sorted = [2, 2, 4, 7, 7, 7];
+            for (i, v) in sorted.iter().enumerate() {
+                assert_eq!(multiset.select(i), Some(v));
+            }
+            assert_eq!(multiset.select(6), None);
+            assert_eq!((multiset.rank(&2), multiset.rank(&3), multiset.rank(&7), multiset.rank(&9)), (0, 2, 3, 6));
+        }
+    }
+}
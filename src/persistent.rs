@@ -0,0 +1,318 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use {TreeF, TreeAlgebra, TreeCoalgebra, SubtreeSize, Reversible, SplayStrategy, Count};
+use {find, find_index, isolate_interval, end, join, splay, zip_tree};
+
+// Persistent trees share structure between versions. Nodes live behind an
+// `Rc`, and separating a node that is still referenced elsewhere copies the
+// value and clones the child pointers instead of taking them. Every operation
+// therefore only copies the nodes along the path it touches, and the tree it
+// started from is left intact. Cloning a whole tree is O(1), so snapshots and
+// undo history are just older values kept around.
+
+#[derive(Debug)]
+pub struct PersistentBranch<A> {
+    val: A,
+    size: Count,
+    reversed: bool,
+    left: PersistentNode<A>,
+    right: PersistentNode<A>,
+}
+
+#[derive(Debug)]
+pub enum PersistentNode<A> {
+    Empty,
+    Branch(Rc<PersistentBranch<A>>),
+}
+
+impl<A> Clone for PersistentNode<A> {
+    fn clone(&self) -> PersistentNode<A> {
+        match *self {
+            PersistentNode::Empty => PersistentNode::Empty,
+            PersistentNode::Branch(ref b) => PersistentNode::Branch(b.clone()),
+        }
+    }
+}
+
+impl<A> TreeAlgebra<A> for PersistentNode<A> {
+    fn combine(input: TreeF<A, PersistentNode<A>>) -> PersistentNode<A> {
+        match input {
+            TreeF::Empty => {
+                PersistentNode::Empty
+            },
+            TreeF::Branch { val, left, right } => {
+                PersistentNode::Branch(Rc::new(PersistentBranch {
+                    val: val,
+                    size: Count::new(get_size(&left) + get_size(&right) + 1),
+                    reversed: false,
+                    left: left,
+                    right: right,
+                }))
+            },
+        }
+    }
+}
+
+impl<A: Clone> TreeCoalgebra<A> for PersistentNode<A> {
+    fn separate(input: PersistentNode<A>) -> TreeF<A, PersistentNode<A>> {
+        match input {
+            PersistentNode::Empty => {
+                TreeF::Empty
+            },
+            PersistentNode::Branch(b) => {
+                let PersistentBranch { val, reversed, left, right, .. } = match Rc::try_unwrap(b) {
+                    Ok(branch) => branch,
+                    Err(shared) => PersistentBranch {
+                        val: shared.val.clone(),
+                        size: shared.size,
+                        reversed: shared.reversed,
+                        left: shared.left.clone(),
+                        right: shared.right.clone(),
+                    },
+                };
+                if reversed {
+                    TreeF::Branch {
+                        val: val,
+                        left: Reversible::reversed(right),
+                        right: Reversible::reversed(left),
+                    }
+                } else {
+                    TreeF::Branch {
+                        val: val,
+                        left: left,
+                        right: right,
+                    }
+                }
+            },
+        }
+    }
+    fn is_branch(input: &PersistentNode<A>) -> bool {
+        match *input {
+            PersistentNode::Empty => false,
+            PersistentNode::Branch(_) => true,
+        }
+    }
+}
+
+impl<A> SubtreeSize for PersistentNode<A> {
+    fn subtree_size(input: &PersistentNode<A>) -> usize {
+        match *input {
+            PersistentNode::Empty => 0,
+            PersistentNode::Branch(ref b) => b.size.get(),
+        }
+    }
+}
+
+impl<A: Clone> Reversible for PersistentNode<A> {
+    fn reversed(input: PersistentNode<A>) -> PersistentNode<A> {
+        match input {
+            PersistentNode::Empty => {
+                PersistentNode::Empty
+            },
+            PersistentNode::Branch(b) => {
+                PersistentNode::Branch(Rc::new(PersistentBranch {
+                    val: b.val.clone(),
+                    size: b.size,
+                    reversed: !b.reversed,
+                    left: b.left.clone(),
+                    right: b.right.clone(),
+                }))
+            },
+        }
+    }
+}
+
+fn get_size<A>(n: &PersistentNode<A>) -> usize {
+    SubtreeSize::subtree_size(n)
+}
+
+fn leaf<A>(val: A) -> PersistentNode<A> {
+    TreeAlgebra::combine(TreeF::Branch {
+        val: val,
+        left: PersistentNode::Empty,
+        right: PersistentNode::Empty,
+    })
+}
+
+// Looks up the element at `index` without restructuring, so that reads never
+// create a new version. Pending reversals are tracked on the way down.
+fn get_index<A>(root: &PersistentNode<A>, index: usize) -> Option<&A> {
+    let mut node = root;
+    let mut remaining = index;
+    let mut flipped = false;
+    while let PersistentNode::Branch(ref b) = *node {
+        flipped ^= b.reversed;
+        let (left, right) = if flipped { (&b.right, &b.left) } else { (&b.left, &b.right) };
+        let left_size = get_size(left);
+        match left_size.cmp(&remaining) {
+            Ordering::Less => {
+                remaining = remaining - left_size - 1;
+                node = right;
+            },
+            Ordering::Equal => return Some(&b.val),
+            Ordering::Greater => node = left,
+        }
+    }
+    None
+}
+
+#[derive(Debug, Clone)]
+pub struct PersistentSplayTree<A> {
+    root: PersistentNode<A>,
+}
+
+impl<A: Ord + Clone> PersistentSplayTree<A> {
+    pub fn new() -> PersistentSplayTree<A> {
+        PersistentSplayTree {
+            root: PersistentNode::Empty,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        get_size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !TreeCoalgebra::is_branch(&self.root)
+    }
+
+    pub fn contains(&self, v: &A) -> bool {
+        let mut node = &self.root;
+        while let PersistentNode::Branch(ref b) = *node {
+            match v.cmp(&b.val) {
+                Ordering::Less => node = &b.left,
+                Ordering::Equal => return true,
+                Ordering::Greater => node = &b.right,
+            }
+        }
+        false
+    }
+
+    pub fn insert(&self, v: A) -> PersistentSplayTree<A> {
+        let mut ins_loc = find(self.root.clone(), &v);
+        if !TreeCoalgebra::is_branch(&ins_loc.here) {
+            ins_loc.here = leaf(v);
+        }
+        PersistentSplayTree {
+            root: zip_tree(splay(ins_loc)),
+        }
+    }
+
+    pub fn remove(&self, v: &A) -> PersistentSplayTree<A> {
+        let root = zip_tree(splay(find(self.root.clone(), v)));
+        match TreeCoalgebra::separate(root) {
+            TreeF::Branch { ref val, left, right } if val == v => {
+                PersistentSplayTree {
+                    root: join(left, right),
+                }
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+impl<A: Ord + Clone> Default for PersistentSplayTree<A> {
+    fn default() -> PersistentSplayTree<A> {
+        PersistentSplayTree::new()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PersistentBitRange {
+    root: PersistentNode<bool>,
+}
+
+impl PersistentBitRange {
+    pub fn new(n: usize) -> PersistentBitRange {
+        let mut root = PersistentNode::Empty;
+        for _ in 0..n {
+            let mut zipper = end(root);
+            zipper.here = leaf(false);
+            root = zip_tree(splay(zipper));
+        }
+        PersistentBitRange {
+            root: root,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        get_size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<bool> {
+        get_index(&self.root, index).cloned()
+    }
+
+    pub fn set(&self, index: usize, val: bool) -> PersistentBitRange {
+        let mut zipper = find_index(self.root.clone(), index);
+        if let TreeF::Branch { left, right, .. } = TreeCoalgebra::separate(zipper.here) {
+            zipper.here = TreeAlgebra::combine(TreeF::Branch {
+                val: val,
+                left: left,
+                right: right,
+            });
+        } else {
+            zipper.here = PersistentNode::Empty;
+        }
+        PersistentBitRange {
+            root: zip_tree(splay(zipper)),
+        }
+    }
+
+    // Inserts `val` so that it ends up at position `index`, shifting the
+    // following bits up by one. Indices past the end append.
+    pub fn insert(&self, index: usize, val: bool) -> PersistentBitRange {
+        if index >= self.len() {
+            let mut zipper = end(self.root.clone());
+            zipper.here = leaf(val);
+            return PersistentBitRange {
+                root: zip_tree(splay(zipper)),
+            };
+        }
+        let root = zip_tree(splay(find_index(self.root.clone(), index)));
+        match TreeCoalgebra::separate(root) {
+            TreeF::Empty => PersistentBitRange::new(0),
+            TreeF::Branch { val: old_val, left, right } => {
+                PersistentBitRange {
+                    root: TreeAlgebra::combine(TreeF::Branch {
+                        val: val,
+                        left: left,
+                        right: TreeAlgebra::combine(TreeF::Branch {
+                            val: old_val,
+                            left: PersistentNode::Empty,
+                            right: right,
+                        }),
+                    }),
+                }
+            },
+        }
+    }
+
+    pub fn remove(&self, index: usize) -> PersistentBitRange {
+        if index >= self.len() {
+            return self.clone();
+        }
+        let root = zip_tree(splay(find_index(self.root.clone(), index)));
+        match TreeCoalgebra::separate(root) {
+            TreeF::Empty => PersistentBitRange::new(0),
+            TreeF::Branch { left, right, .. } => {
+                PersistentBitRange {
+                    root: join(left, right),
+                }
+            },
+        }
+    }
+
+    pub fn reverse_range(&self, index_start: usize, index_end: usize) -> PersistentBitRange {
+        let mut zipper = isolate_interval(self.root.clone(), index_start, index_end, SplayStrategy::BottomUp);
+        zipper.here = Reversible::reversed(zipper.here);
+        PersistentBitRange {
+            root: zip_tree(zipper),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use std::fmt::Display;
+
+use {TreeF, TreeCoalgebra, TreeNode, BitOp, BitRangeNode};
+
+// Terminal rendering of tree shapes with box-drawing characters. Sideways
+// output puts the root on the left with the right subtree above it and the
+// left subtree below, one node per line, and stays readable for trees of any
+// shape. Top-down output is the familiar picture with the root on top, but
+// grows as wide as the tree has nodes.
+//
+// Either way the tree is first flattened into a list of labels in pre-order,
+// so that deep trees are walked with an explicit stack rather than by
+// recursion, and each parent comes before its children.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    Sideways,
+    TopDown,
+}
+
+// The child order to show for nodes with pending reversals. Logical order is
+// the sequence order the tree represents; physical order is how the children
+// are stored right now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildOrder {
+    Logical,
+    Physical,
+}
+
+struct Labelled {
+    label: String,
+    left: Option<usize>,
+    right: Option<usize>,
+}
+
+// Flattens a tree given how to split a node into a label and its children.
+// `split` returns None for empty nodes.
+fn flatten<N, F>(root: N, mut split: F) -> Vec<Labelled>
+    where F: FnMut(N) -> Option<(String, N, N)> {
+    let mut nodes: Vec<Labelled> = Vec::new();
+    // Each entry is a node still to be split, along with the index of its
+    // parent and whether it is the parent's left child.
+    let mut stack: Vec<(N, Option<(usize, bool)>)> = vec![(root, None)];
+    while let Some((node, parent)) = stack.pop() {
+        let (label, left, right) = match split(node) {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let index = nodes.len();
+        nodes.push(Labelled {
+            label: label,
+            left: None,
+            right: None,
+        });
+        match parent {
+            Some((p, true)) => nodes[p].left = Some(index),
+            Some((p, false)) => nodes[p].right = Some(index),
+            None => {},
+        }
+        stack.push((right, Some((index, false))));
+        stack.push((left, Some((index, true))));
+    }
+    nodes
+}
+
+fn draw(nodes: &[Labelled], layout: Layout) -> String {
+    if nodes.is_empty() {
+        return String::from("(empty)\n");
+    }
+    match layout {
+        Layout::Sideways => draw_sideways(nodes),
+        Layout::TopDown => draw_top_down(nodes),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Side {
+    Root,
+    Left,
+    Right,
+}
+
+enum Step {
+    Visit(usize, String, Side),
+    Label(usize, String, Side),
+}
+
+fn draw_sideways(nodes: &[Labelled]) -> String {
+    let mut out = String::new();
+    let mut stack = vec![Step::Visit(0, String::new(), Side::Root)];
+    while let Some(step) = stack.pop() {
+        match step {
+            Step::Visit(i, prefix, side) => {
+                // A child's connector continues past this node's line only
+                // towards the side of the tree that this node hangs from.
+                let extend = |towards: Side| {
+                    match side {
+                        Side::Root => prefix.clone(),
+                        _ if side == towards => format!("{}    ", prefix),
+                        _ => format!("{}│   ", prefix),
+                    }
+                };
+                if let Some(left) = nodes[i].left {
+                    stack.push(Step::Visit(left, extend(Side::Left), Side::Left));
+                }
+                stack.push(Step::Label(i, prefix.clone(), side));
+                if let Some(right) = nodes[i].right {
+                    stack.push(Step::Visit(right, extend(Side::Right), Side::Right));
+                }
+            },
+            Step::Label(i, prefix, side) => {
+                out.push_str(&prefix);
+                out.push_str(match side {
+                    Side::Root => "",
+                    Side::Left => "└── ",
+                    Side::Right => "┌── ",
+                });
+                out.push_str(&nodes[i].label);
+                out.push('\n');
+            },
+        }
+    }
+    out
+}
+
+// A rendered subtree: its lines, padded to a common width, and the column
+// its root's connector attaches to.
+struct Block {
+    lines: Vec<Vec<char>>,
+    width: usize,
+    anchor: usize,
+}
+
+fn draw_top_down(nodes: &[Labelled]) -> String {
+    let mut blocks: Vec<Option<Block>> = (0..nodes.len()).map(|_| None).collect();
+    // Children always come after their parent, so going backwards builds
+    // every block after the blocks of its subtrees.
+    for i in (0..nodes.len()).rev() {
+        let left = nodes[i].left.and_then(|l| blocks[l].take());
+        let right = nodes[i].right.and_then(|r| blocks[r].take());
+        blocks[i] = Some(join_blocks(&nodes[i].label, left, right));
+    }
+    let root = blocks[0].take().unwrap();
+    let mut out = String::new();
+    for line in root.lines {
+        let line: String = line.into_iter().collect();
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn join_blocks(label: &str, left: Option<Block>, right: Option<Block>) -> Block {
+    // Connectors need a column to attach to, even under an empty label.
+    let mut label: Vec<char> = label.chars().collect();
+    if label.is_empty() {
+        label.push(' ');
+    }
+    let (left_width, right_width) = (left.as_ref().map_or(0, |b| b.width), right.as_ref().map_or(0, |b| b.width));
+    let width = left_width + label.len() + right_width;
+    let anchor = left_width + label.len() / 2;
+    let mut top = vec![' '; width];
+    top[left_width..left_width + label.len()].copy_from_slice(&label);
+    let mut lines = vec![top];
+    if left.is_none() && right.is_none() {
+        return Block {
+            lines: lines,
+            width: width,
+            anchor: anchor,
+        };
+    }
+    let mut connector = vec![' '; width];
+    if let Some(ref left) = left {
+        connector[left.anchor] = '┌';
+        for c in &mut connector[left.anchor + 1..anchor] {
+            *c = '─';
+        }
+    }
+    if let Some(ref right) = right {
+        let end = left_width + label.len() + right.anchor;
+        for c in &mut connector[anchor + 1..end] {
+            *c = '─';
+        }
+        connector[end] = '┐';
+    }
+    connector[anchor] = match (&left, &right) {
+        (&Some(_), &Some(_)) => '┴',
+        (&Some(_), &None) => '┘',
+        _ => '└',
+    };
+    lines.push(connector);
+    let depth = left.as_ref().map_or(0, |b| b.lines.len()).max(right.as_ref().map_or(0, |b| b.lines.len()));
+    for k in 0..depth {
+        let mut line = Vec::with_capacity(width);
+        match left {
+            Some(ref b) if k < b.lines.len() => line.extend_from_slice(&b.lines[k]),
+            _ => line.resize(left_width, ' '),
+        }
+        line.resize(left_width + label.len(), ' ');
+        if let Some(ref b) = right {
+            if k < b.lines.len() {
+                line.extend_from_slice(&b.lines[k]);
+            }
+        }
+        line.resize(width, ' ');
+        lines.push(line);
+    }
+    Block {
+        lines: lines,
+        width: width,
+        anchor: anchor,
+    }
+}
+
+// Renders any tree that can be taken apart, consuming it. Nodes are shown in
+// the order `separate` produces, so pending work such as a reversal is
+// applied on the way.
+pub fn render<A: Display, B: TreeCoalgebra<A>>(tree: B, layout: Layout) -> String {
+    let nodes = flatten(tree, |node| {
+        match TreeCoalgebra::separate(node) {
+            TreeF::Empty => None,
+            TreeF::Branch { val, left, right } => Some((val.to_string(), left, right)),
+        }
+    });
+    draw(&nodes, layout)
+}
+
+impl<A: Display> TreeNode<A> {
+    pub fn pretty(&self, layout: Layout) -> String {
+        let nodes = flatten(self, |node| {
+            match *node {
+                TreeNode(TreeF::Empty) => None,
+                TreeNode(TreeF::Branch { ref val, ref left, ref right }) => Some((val.to_string(), &**left, &**right)),
+            }
+        });
+        draw(&nodes, layout)
+    }
+}
+
+impl BitRangeNode {
+    // In logical order each node shows the bit it currently stands for and
+    // the size of its subtree. In physical order it shows what is stored,
+    // marking pending reversals and updates that its children still owe.
+    pub fn pretty(&self, layout: Layout, order: ChildOrder) -> String {
+        let nodes = flatten((self, false, BitOp::Keep), |(node, flipped, op)| {
+            match *node {
+                BitRangeNode::Empty => None,
+                BitRangeNode::Branch { here, size, reversed, pending, ref left, ref right, .. } => {
+                    let (left, right) = (&**left, &**right);
+                    match order {
+                        ChildOrder::Physical => {
+                            let mut label = format!("{} ({})", here as u8, size);
+                            if reversed {
+                                label.push_str(" rev");
+                            }
+                            if pending != BitOp::Keep {
+                                label.push_str(&format!(" {}", pending));
+                            }
+                            Some((label, (left, false, BitOp::Keep), (right, false, BitOp::Keep)))
+                        },
+                        ChildOrder::Logical => {
+                            let label = format!("{} ({})", op.apply(here) as u8, size);
+                            let flipped = flipped ^ reversed;
+                            let op = pending.then(op);
+                            if flipped {
+                                Some((label, (right, flipped, op), (left, flipped, op)))
+                            } else {
+                                Some((label, (left, flipped, op), (right, flipped, op)))
+                            }
+                        },
+                    }
+                },
+            }
+        });
+        draw(&nodes, layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {TreeAlgebra, Reversible};
+    use super::*;
+
+    fn node(val: i32, left: TreeNode<i32>, right: TreeNode<i32>) -> TreeNode<i32> {
+        TreeAlgebra::combine(TreeF::Branch {
+            val: val,
+            left: left,
+            right: right,
+        })
+    }
+
+    fn leaf(val: i32) -> TreeNode<i32> {
+        node(val, TreeNode(TreeF::Empty), TreeNode(TreeF::Empty))
+    }
+
+    // 4 at the root, 2 on the left with children 1 and 3, and 5 on the right.
+    fn sample() -> TreeNode<i32> {
+        node(4, node(2, leaf(1), leaf(3)), leaf(5))
+    }
+
+    #[test]
+    fn draws_sideways() {
+        assert_eq!(sample().pretty(Layout::Sideways), "\
+┌── 5
+4
+│   ┌── 3
+└── 2
+    └── 1
+");
+    }
+
+    #[test]
+    fn draws_top_down() {
+        assert_eq!(sample().pretty(Layout::TopDown), "   4
+ ┌─┴┐
+ 2  5
+┌┴┐
+1 3
+");
+        assert_eq!(node(1, TreeNode(TreeF::Empty), leaf(2)).pretty(Layout::TopDown), "1\n└┐\n 2\n");
+    }
+
+    #[test]
+    fn draws_empty_labels() {
+        let text = |val: &str, left, right| -> TreeNode<String> {
+            TreeAlgebra::combine(TreeF::Branch {
+                val: val.to_string(),
+                left: left,
+                right: right,
+            })
+        };
+        let empty = || TreeNode(TreeF::Empty);
+        let tree = text("", text("a", empty(), empty()), text("", empty(), empty()));
+        assert_eq!(tree.pretty(Layout::TopDown), "\n┌┴┐\na\n");
+        assert_eq!(tree.pretty(Layout::Sideways), "┌── \n\n└── a\n");
+        let tree = text("", empty(), text("", empty(), empty()));
+        assert_eq!(tree.pretty(Layout::TopDown), "\n└┐\n\n");
+    }
+
+    #[test]
+    fn consuming_render_matches_borrowed() {
+        for &layout in &[Layout::Sideways, Layout::TopDown] {
+            assert_eq!(render(sample(), layout), sample().pretty(layout));
+        }
+        assert_eq!(render(TreeNode::<i32>(TreeF::Empty), Layout::TopDown), "(empty)\n");
+    }
+
+    #[test]
+    fn bit_ranges_show_either_order() {
+        let bit = |val| -> BitRangeNode {
+            TreeAlgebra::combine(TreeF::Branch {
+                val: val,
+                left: BitRangeNode::Empty,
+                right: BitRangeNode::Empty,
+            })
+        };
+        let tree = TreeAlgebra::combine(TreeF::Branch {
+            val: false,
+            left: bit(true),
+            right: BitRangeNode::Empty,
+        });
+        let tree = BitRangeNode::updated(Reversible::reversed(tree), BitOp::Flip);
+        assert_eq!(tree.pretty(Layout::Sideways, ChildOrder::Physical), "1 (2) rev flip\n└── 1 (1)\n");
+        assert_eq!(tree.pretty(Layout::Sideways, ChildOrder::Logical), "┌── 0 (1)\n1 (2)\n");
+    }
+}
@@ -0,0 +1,298 @@
+use std::io::{self, BufRead, Write};
+
+use rust_splay::{BitRange, SplayPolicy, SplayStrategy, SplayTree, Splay};
+use rust_splay::command::parse_command;
+use rust_splay::pretty::{ChildOrder, Layout};
+
+use {execute, DriverError, ExecError, USAGE};
+
+// An interactive session against a live tree. Each line is run as soon as it
+// is read and its result printed straight away; with `--show-tree` the shape
+// of the tree is printed after every command as well, so the effect of each
+// splay can be followed step by step.
+//
+// Against a set (the default) the commands are `insert X`, `contains X` and
+// `splay X` for integers X. Against a bit sequence (`--bits N`) they are the
+// driver's commands, see the `command` module. In both modes lines starting
+// with `:` control the session: `:tree` prints the tree once, `:show` toggles
+// printing it after every command, `:layout` switches between sideways and
+// top-down drawings, `:order` between the physical and logical child order of
+// bit sequences, `:stats` prints the splay statistics gathered so far,
+// `:help` lists the commands and `:quit` ends the session, as
+// does the end of input.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    Set,
+    Bits(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplOptions {
+    pub target: Target,
+    pub strategy: SplayStrategy,
+    pub policy: SplayPolicy,
+    pub show_tree: bool,
+    pub layout: Layout,
+}
+
+// How the tree is drawn.
+#[derive(Debug, Clone, Copy)]
+struct View {
+    layout: Layout,
+    order: ChildOrder,
+}
+
+const HELP: &str = "set commands: insert X, contains X, splay X
+bit commands: S I B, G I, R L R, I I B, D I, C L R, F L R, A L R B, P, M L R T
+session: :tree, :show, :layout, :order, :stats, :help, :quit";
+
+enum Session {
+    Set(SplayTree<i64>),
+    Bits(BitRange),
+}
+
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<ReplOptions, DriverError> {
+    let mut options = ReplOptions {
+        target: Target::Set,
+        strategy: SplayStrategy::BottomUp,
+        policy: SplayPolicy::Full,
+        show_tree: false,
+        layout: Layout::Sideways,
+    };
+    let mut args = args;
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next().ok_or_else(|| DriverError::Usage(format!("missing value for `{}`\n{}", name, USAGE)))
+        };
+        match arg.as_str() {
+            "--set" => options.target = Target::Set,
+            "--bits" => {
+                let n = value("--bits")?;
+                match n.parse::<usize>() {
+                    Ok(n) => options.target = Target::Bits(n),
+                    _ => return Err(DriverError::Usage(format!("invalid sequence length `{}`\n{}", n, USAGE))),
+                }
+            },
+            "--top-down" => options.strategy = SplayStrategy::TopDown,
+            "--bottom-up" => options.strategy = SplayStrategy::BottomUp,
+            "--policy" => {
+                let name = value("--policy")?;
+                options.policy = parse_policy(&name)
+                    .ok_or_else(|| DriverError::Usage(format!("unknown splay policy `{}`\n{}", name, USAGE)))?;
+            },
+            "--show-tree" => options.show_tree = true,
+            "--top-down-view" => options.layout = Layout::TopDown,
+            _ => return Err(DriverError::Usage(format!("unexpected argument `{}`\n{}", arg, USAGE))),
+        }
+    }
+    Ok(options)
+}
+
+// Policies are named `full`, `semi`, `no-splay-on-read` or `depth=N`.
+fn parse_policy(name: &str) -> Option<SplayPolicy> {
+    match name {
+        "full" => Some(SplayPolicy::Full),
+        "semi" => Some(SplayPolicy::SemiSplay),
+        "no-splay-on-read" => Some(SplayPolicy::NoSplayOnRead),
+        _ if name.starts_with("depth=") => name["depth=".len()..].parse().ok().map(SplayPolicy::DepthThreshold),
+        _ => None,
+    }
+}
+
+pub fn run(options: ReplOptions) -> Result<(), DriverError> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    session(stdin.lock(), stdout.lock(), options)
+}
+
+fn session<R: BufRead, W: Write>(mut input: R, mut output: W, options: ReplOptions) -> Result<(), DriverError> {
+    let mut tree = match options.target {
+        Target::Set => {
+            let mut tree = SplayTree::with_strategy(options.strategy);
+            tree.set_policy(options.policy);
+            tree.enable_stats();
+            Session::Set(tree)
+        },
+        Target::Bits(n) => {
+            let mut range = BitRange::with_strategy(n, options.strategy);
+            range.set_policy(options.policy);
+            range.enable_stats();
+            Session::Bits(range)
+        },
+    };
+    let mut show_tree = options.show_tree;
+    let mut view = View {
+        layout: options.layout,
+        order: ChildOrder::Physical,
+    };
+    let mut buf = String::new();
+    loop {
+        write!(output, "> ").and_then(|_| output.flush()).map_err(DriverError::Write)?;
+        buf.clear();
+        if input.read_line(&mut buf).map_err(DriverError::Read)? == 0 {
+            break;
+        }
+        let line = buf.trim();
+        let ran = match line {
+            "" => false,
+            ":quit" => break,
+            ":help" => {
+                writeln!(output, "{}", HELP).map_err(DriverError::Write)?;
+                false
+            },
+            ":tree" => {
+                write!(output, "{}", render(&tree, view)).map_err(DriverError::Write)?;
+                false
+            },
+            ":show" => {
+                show_tree = !show_tree;
+                writeln!(output, "showing the tree after each command: {}", if show_tree { "on" } else { "off" })
+                    .map_err(DriverError::Write)?;
+                false
+            },
+            ":stats" => {
+                let stats = match tree {
+                    Session::Set(ref tree) => tree.stats(),
+                    Session::Bits(ref range) => range.stats(),
+                };
+                if let Some(stats) = stats {
+                    writeln!(output, "{}", stats).map_err(DriverError::Write)?;
+                }
+                false
+            },
+            ":layout" => {
+                view.layout = match view.layout {
+                    Layout::Sideways => Layout::TopDown,
+                    Layout::TopDown => Layout::Sideways,
+                };
+                writeln!(output, "layout: {:?}", view.layout).map_err(DriverError::Write)?;
+                false
+            },
+            ":order" => {
+                view.order = match view.order {
+                    ChildOrder::Physical => ChildOrder::Logical,
+                    ChildOrder::Logical => ChildOrder::Physical,
+                };
+                writeln!(output, "child order: {:?}", view.order).map_err(DriverError::Write)?;
+                false
+            },
+            _ if line.starts_with(':') => {
+                writeln!(output, "error: unknown session command `{}`", line).map_err(DriverError::Write)?;
+                false
+            },
+            _ => step(&mut tree, line, &mut output)?,
+        };
+        if ran && show_tree {
+            write!(output, "{}", render(&tree, view)).map_err(DriverError::Write)?;
+        }
+    }
+    output.flush().map_err(DriverError::Write)
+}
+
+// Runs one command, printing its result or what was wrong with it. Returns
+// whether the command ran, so that the tree is only shown when it may have
+// changed.
+fn step<W: Write>(tree: &mut Session, line: &str, output: &mut W) -> Result<bool, DriverError> {
+    let result = match *tree {
+        Session::Set(ref mut tree) => {
+            let mut words = line.split_whitespace();
+            let name = words.next().unwrap_or("");
+            let value = words.next().map(|word| word.parse::<i64>());
+            match (name, value) {
+                (_, None) => Err(String::from("expected a command and a value, try :help")),
+                (_, Some(Err(why))) => Err(format!("invalid number: {}", why)),
+                ("insert", Some(Ok(v))) => {
+                    tree.insert(v);
+                    Ok(String::from("ok"))
+                },
+                ("contains", Some(Ok(v))) => Ok(format!("{}", tree.contains(v))),
+                ("splay", Some(Ok(v))) => {
+                    tree.splay_to_root(v);
+                    Ok(String::from("ok"))
+                },
+                _ => Err(format!("unknown command `{}`, try :help", name)),
+            }
+        },
+        Session::Bits(ref mut range) => {
+            match parse_command(line) {
+                Err(error) => Err(error.to_string()),
+                Ok(command) => {
+                    match execute(range, command, output) {
+                        Ok(()) => return Ok(true),
+                        Err(ExecError::Write(why)) => return Err(DriverError::Write(why)),
+                        Err(ExecError::OutOfRange(index)) => Err(format!("index {} is out of range", index)),
+                    }
+                },
+            }
+        },
+    };
+    match result {
+        Ok(message) => {
+            writeln!(output, "{}", message).map_err(DriverError::Write)?;
+            Ok(true)
+        },
+        Err(why) => {
+            writeln!(output, "error: {}", why).map_err(DriverError::Write)?;
+            Ok(false)
+        },
+    }
+}
+
+fn render(tree: &Session, view: View) -> String {
+    match *tree {
+        Session::Set(ref tree) => tree.root().pretty(view.layout),
+        Session::Bits(ref range) => range.root().pretty(view.layout, view.order),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session_str(input: &str, options: ReplOptions) -> String {
+        let mut output = Vec::new();
+        session(input.as_bytes(), &mut output, options).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn repl_args(args: &[&str]) -> Result<ReplOptions, DriverError> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn set_session_shows_the_tree() {
+        let options = repl_args(&["--show-tree"]).unwrap();
+        let output = session_str("insert 2\ninsert 1\ncontains 2\nfoo 1\n:quit\ninsert 3\n", options);
+        assert_eq!(output, "> ok\n2\n> ok\n┌── 2\n1\n> true\n2\n└── 1\n> error: unknown command `foo`, try :help\n> ");
+    }
+
+    #[test]
+    fn bit_session_runs_driver_commands() {
+        let options = repl_args(&["--bits", "3", "--top-down"]).unwrap();
+        let output = session_str("S 1 1\nP\nG 7\nX\n", options);
+        assert_eq!(output, "> > 010\n> error: index 7 is out of range\n> error: column 1: unknown command `X`\n> ");
+    }
+
+    #[test]
+    fn toggles_showing_the_tree() {
+        let options = repl_args(&["--bits", "1"]).unwrap();
+        let output = session_str(":show\nR 0 1\n:show\nF 0 1\n:tree\n:order\n:tree\n", options);
+        assert_eq!(output, "> showing the tree after each command: on\n> 0 (1) rev\n\
+                            > showing the tree after each command: off\n> > 1 (1) rev flip\n\
+                            > child order: Logical\n> 1 (1)\n> ");
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = repl_args(&["--policy", "depth=4", "--bits", "8"]).unwrap();
+        assert_eq!(options.policy, SplayPolicy::DepthThreshold(4));
+        assert_eq!(options.target, Target::Bits(8));
+        assert_eq!(options.strategy, SplayStrategy::BottomUp);
+        assert_eq!(options.layout, Layout::Sideways);
+        assert_eq!(repl_args(&["--top-down-view"]).unwrap().layout, Layout::TopDown);
+        assert!(repl_args(&["--policy", "sometimes"]).is_err());
+        assert!(repl_args(&["--bits"]).is_err());
+        assert!(repl_args(&["--bits", "-1"]).is_err());
+    }
+}
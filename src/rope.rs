@@ -0,0 +1,526 @@
+use std::cmp::Ordering;
+use std::fmt::{self, Write};
+use std::mem;
+use std::ops::RangeBounds;
+
+use {TreeF, TreeAlgebra, TreeCoalgebra, Reversible, SplayStrategy, IndexError};
+use {build_balanced, check_insert_index, check_range, join, splay_by};
+use stats;
+
+// A text buffer kept as an implicit splay tree of UTF-8 chunks, much like
+// `BitRange` keeps bits. Each node stores the number of bytes, chars and
+// newlines in its subtree, so that chars and lines can be found by offset,
+// and editing anywhere takes amortized O(log n) plus the size of a chunk.
+// Offsets are in chars throughout.
+//
+// Reversal works on chars, like reversing a `Vec<char>`: combining marks end
+// up before the char they modify, and "\r\n" turns into "\n\r". Unlike in a
+// bit range, a node's reversal flag covers its own chunk as well as its
+// children, since a reversed chunk reads backwards.
+
+const CHUNK_BYTES: usize = 128;
+
+#[derive(Debug)]
+pub enum RopeNode {
+    Empty,
+    Branch {
+        chunk: String,
+        bytes: usize,
+        chars: usize,
+        lines: usize,
+        reversed: bool,
+        left: Box<RopeNode>,
+        right: Box<RopeNode>,
+    },
+}
+
+impl TreeAlgebra<String> for RopeNode {
+    fn combine(input: TreeF<String, RopeNode>) -> RopeNode {
+        match input {
+            TreeF::Empty => {
+                RopeNode::Empty
+            },
+            TreeF::Branch { val, left, right } => {
+                stats::record(|stats| stats.allocations += 2);
+                RopeNode::Branch {
+                    bytes: get_bytes(&left) + get_bytes(&right) + val.len(),
+                    chars: get_chars(&left) + get_chars(&right) + val.chars().count(),
+                    lines: get_lines(&left) + get_lines(&right) + newlines(&val),
+                    chunk: val,
+                    reversed: false,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            },
+        }
+    }
+}
+
+impl TreeCoalgebra<String> for RopeNode {
+    fn separate(input: RopeNode) -> TreeF<String, RopeNode> {
+        match input {
+            RopeNode::Empty => {
+                TreeF::Empty
+            },
+            RopeNode::Branch { chunk, reversed, left, right, .. } => {
+                if reversed {
+                    TreeF::Branch {
+                        val: chunk.chars().rev().collect(),
+                        left: Reversible::reversed(*right),
+                        right: Reversible::reversed(*left),
+                    }
+                } else {
+                    TreeF::Branch {
+                        val: chunk,
+                        left: *left,
+                        right: *right,
+                    }
+                }
+            },
+        }
+    }
+    fn is_branch(input: &RopeNode) -> bool {
+        match *input {
+            RopeNode::Empty => false,
+            RopeNode::Branch {..} => true,
+        }
+    }
+}
+
+impl Reversible for RopeNode {
+    fn reversed(input: RopeNode) -> RopeNode {
+        match input {
+            RopeNode::Empty => {
+                RopeNode::Empty
+            },
+            RopeNode::Branch { chunk, bytes, chars, lines, reversed, left, right } => {
+                RopeNode::Branch {
+                    chunk: chunk,
+                    bytes: bytes,
+                    chars: chars,
+                    lines: lines,
+                    reversed: !reversed,
+                    left: left,
+                    right: right,
+                }
+            },
+        }
+    }
+}
+
+fn get_bytes(n: &RopeNode) -> usize {
+    match *n {
+        RopeNode::Empty => 0,
+        RopeNode::Branch { bytes, .. } => bytes,
+    }
+}
+
+fn get_chars(n: &RopeNode) -> usize {
+    match *n {
+        RopeNode::Empty => 0,
+        RopeNode::Branch { chars, .. } => chars,
+    }
+}
+
+fn get_lines(n: &RopeNode) -> usize {
+    match *n {
+        RopeNode::Empty => 0,
+        RopeNode::Branch { lines, .. } => lines,
+    }
+}
+
+fn newlines(s: &str) -> usize {
+    s.bytes().filter(|&b| b == b'\n').count()
+}
+
+// The byte offset of char `index` in `s`, or its length if there are not
+// that many chars.
+fn byte_offset(s: &str, index: usize) -> usize {
+    s.char_indices().nth(index).map_or(s.len(), |(at, _)| at)
+}
+
+fn leaf(chunk: String, left: RopeNode, right: RopeNode) -> RopeNode {
+    TreeAlgebra::combine(TreeF::Branch {
+        val: chunk,
+        left: left,
+        right: right,
+    })
+}
+
+// Cuts `text` into chunks at char boundaries and builds a balanced tree of
+// them.
+fn build(text: &str) -> RopeNode {
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    while !rest.is_empty() {
+        let mut at = rest.len().min(CHUNK_BYTES);
+        while !rest.is_char_boundary(at) {
+            at -= 1;
+        }
+        chunks.push(&rest[..at]);
+        rest = &rest[at..];
+    }
+    let mut chunks = chunks.into_iter();
+    let result: Result<RopeNode, ()> = build_balanced(chunks.len(), &mut || Ok(chunks.next().unwrap().to_string()));
+    result.unwrap()
+}
+
+// Splays the chunk holding char `offset` to the root. With `at_end`, an
+// offset just past a chunk also counts as in it.
+fn splay_char(root: RopeNode, offset: usize, at_end: bool, strategy: SplayStrategy) -> RopeNode {
+    let mut remaining = offset;
+    splay_by(root, |chunk: &String, left| {
+        let left_chars = get_chars(left);
+        let chunk_chars = chunk.chars().count();
+        if remaining < left_chars {
+            Ordering::Less
+        } else if remaining < left_chars + chunk_chars || (at_end && remaining == left_chars + chunk_chars) {
+            Ordering::Equal
+        } else {
+            remaining -= left_chars + chunk_chars;
+            Ordering::Greater
+        }
+    }, strategy)
+}
+
+// Splits a tree into the first `offset` chars and the rest.
+fn split_at(root: RopeNode, offset: usize, strategy: SplayStrategy) -> (RopeNode, RopeNode) {
+    if offset == 0 {
+        return (RopeNode::Empty, root);
+    }
+    if offset >= get_chars(&root) {
+        return (root, RopeNode::Empty);
+    }
+    match TreeCoalgebra::separate(splay_char(root, offset, false, strategy)) {
+        TreeF::Empty => (RopeNode::Empty, RopeNode::Empty),
+        TreeF::Branch { val: mut chunk, left, right } => {
+            let local = offset - get_chars(&left);
+            if local == 0 {
+                return (left, leaf(chunk, RopeNode::Empty, right));
+            }
+            let tail = chunk.split_off(byte_offset(&chunk, local));
+            (leaf(chunk, left, RopeNode::Empty), leaf(tail, RopeNode::Empty, right))
+        },
+    }
+}
+
+// The chunks of a tree in order, each with whether it reads backwards. The
+// stack holds chunks whose earlier side is being visited, together with
+// their later side.
+struct Chunks<'a> {
+    stack: Vec<(&'a str, bool, &'a RopeNode)>,
+    node: &'a RopeNode,
+    flipped: bool,
+}
+
+impl<'a> Chunks<'a> {
+    fn new(root: &'a RopeNode) -> Chunks<'a> {
+        Chunks {
+            stack: Vec::new(),
+            node: root,
+            flipped: false,
+        }
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = (&'a str, bool);
+
+    fn next(&mut self) -> Option<(&'a str, bool)> {
+        let mut node = self.node;
+        while let RopeNode::Branch { ref chunk, reversed, ref left, ref right, .. } = *node {
+            self.flipped ^= reversed;
+            let (first, second) = if self.flipped { (right, left) } else { (left, right) };
+            self.stack.push((chunk.as_str(), self.flipped, &**second));
+            node = first;
+        }
+        let (chunk, flipped, next) = self.stack.pop()?;
+        self.node = next;
+        self.flipped = flipped;
+        Some((chunk, flipped))
+    }
+}
+
+#[derive(Debug)]
+pub struct SplayRope {
+    root: RopeNode,
+    strategy: SplayStrategy,
+}
+
+impl SplayRope {
+    pub fn new() -> SplayRope {
+        SplayRope::with_strategy(SplayStrategy::BottomUp)
+    }
+
+    pub fn with_strategy(strategy: SplayStrategy) -> SplayRope {
+        SplayRope {
+            root: RopeNode::Empty,
+            strategy: strategy,
+        }
+    }
+
+    pub fn root(&self) -> &RopeNode {
+        &self.root
+    }
+
+    pub fn len_bytes(&self) -> usize {
+        get_bytes(&self.root)
+    }
+
+    pub fn len_chars(&self) -> usize {
+        get_chars(&self.root)
+    }
+
+    // Lines are separated by '\n', so there is always at least one.
+    pub fn len_lines(&self) -> usize {
+        get_lines(&self.root) + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len_bytes() == 0
+    }
+
+    // Inserts `text` so that it starts at char `index`.
+    pub fn insert(&mut self, index: usize, text: &str) -> Result<(), IndexError> {
+        check_insert_index(index, self.len_chars())?;
+        if text.is_empty() {
+            return Ok(());
+        }
+        let old_root = mem::replace(&mut self.root, RopeNode::Empty);
+        if text.len() < CHUNK_BYTES && TreeCoalgebra::is_branch(&old_root) {
+            // Small insertions go into the chunk they land in if it has room,
+            // so typing a char at a time does not leave a node per char.
+            if let TreeF::Branch { val: mut chunk, left, right } =
+                TreeCoalgebra::separate(splay_char(old_root, index, true, self.strategy)) {
+                if chunk.len() + text.len() <= CHUNK_BYTES {
+                    let at = byte_offset(&chunk, index - get_chars(&left));
+                    chunk.insert_str(at, text);
+                    self.root = leaf(chunk, left, right);
+                } else {
+                    self.root = leaf(chunk, left, right);
+                    self.insert_node(index, build(text));
+                }
+            }
+            return Ok(());
+        }
+        self.root = old_root;
+        self.insert_node(index, build(text));
+        Ok(())
+    }
+
+    fn insert_node(&mut self, index: usize, node: RopeNode) {
+        let old_root = mem::replace(&mut self.root, RopeNode::Empty);
+        let (before, after) = split_at(old_root, index, self.strategy);
+        self.root = join(join(before, node), after);
+    }
+
+    // Cuts out the chars in `range`, returning the tree before it, the range
+    // itself and the tree after it.
+    fn isolate<R: RangeBounds<usize>>(&mut self, range: R) -> Result<(RopeNode, RopeNode, RopeNode), IndexError> {
+        let (start, end) = check_range(range, self.len_chars())?;
+        let old_root = mem::replace(&mut self.root, RopeNode::Empty);
+        let (before, rest) = split_at(old_root, start, self.strategy);
+        let (middle, after) = split_at(rest, end - start, self.strategy);
+        Ok((before, middle, after))
+    }
+
+    pub fn remove<R: RangeBounds<usize>>(&mut self, range: R) -> Result<(), IndexError> {
+        let (before, _, after) = self.isolate(range)?;
+        self.root = join(before, after);
+        Ok(())
+    }
+
+    pub fn reverse<R: RangeBounds<usize>>(&mut self, range: R) -> Result<(), IndexError> {
+        let (before, middle, after) = self.isolate(range)?;
+        self.root = join(join(before, Reversible::reversed(middle)), after);
+        Ok(())
+    }
+
+    // The text of the chars in `range`. The chunk holding the first of them
+    // is splayed to the root, and the rest are read off its right subtree,
+    // so only as much of the tree is walked as the slice needs.
+    pub fn slice<R: RangeBounds<usize>>(&mut self, range: R) -> Result<String, IndexError> {
+        let (start, end) = check_range(range, self.len_chars())?;
+        let mut out = String::new();
+        if start == end {
+            return Ok(out);
+        }
+        let old_root = mem::replace(&mut self.root, RopeNode::Empty);
+        self.root = splay_char(old_root, start, false, self.strategy);
+        if let RopeNode::Branch { ref chunk, ref left, ref right, .. } = self.root {
+            // The root was rebuilt by the splay, so its chunk reads forwards.
+            let mut remaining = end - start;
+            let skip = start - get_chars(left);
+            for c in chunk.chars().skip(skip).take(remaining) {
+                out.push(c);
+                remaining -= 1;
+            }
+            let mut chunks = Chunks::new(right);
+            while remaining > 0 {
+                let (chunk, backwards) = match chunks.next() {
+                    Some(next) => next,
+                    None => break,
+                };
+                let chars: Box<dyn Iterator<Item = char>> = if backwards { Box::new(chunk.chars().rev()) } else { Box::new(chunk.chars()) };
+                for c in chars.take(remaining) {
+                    out.push(c);
+                    remaining -= 1;
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    // The char offset at which line `line` starts.
+    pub fn line_to_char(&mut self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return Some(0);
+        }
+        if line >= self.len_lines() {
+            return None;
+        }
+        // Find the chunk holding the newline that ends the previous line.
+        let mut remaining = line;
+        let old_root = mem::replace(&mut self.root, RopeNode::Empty);
+        self.root = splay_by(old_root, |chunk: &String, left| {
+            let left_lines = get_lines(left);
+            let chunk_lines = newlines(chunk);
+            if remaining <= left_lines {
+                Ordering::Less
+            } else if remaining <= left_lines + chunk_lines {
+                Ordering::Equal
+            } else {
+                remaining -= left_lines + chunk_lines;
+                Ordering::Greater
+            }
+        }, self.strategy);
+        match self.root {
+            RopeNode::Empty => None,
+            RopeNode::Branch { ref chunk, ref left, .. } => {
+                let (at, _) = chunk.match_indices('\n').nth(line - get_lines(left) - 1)?;
+                Some(get_chars(left) + chunk[..at].chars().count() + 1)
+            },
+        }
+    }
+
+    // The line that char `index` is on. An index at the end is on the last
+    // line.
+    pub fn char_to_line(&mut self, index: usize) -> Result<usize, IndexError> {
+        check_insert_index(index, self.len_chars())?;
+        if index == self.len_chars() {
+            return Ok(get_lines(&self.root));
+        }
+        let old_root = mem::replace(&mut self.root, RopeNode::Empty);
+        self.root = splay_char(old_root, index, false, self.strategy);
+        match self.root {
+            RopeNode::Empty => Ok(0),
+            RopeNode::Branch { ref chunk, ref left, .. } => {
+                let at = byte_offset(chunk, index - get_chars(left));
+                Ok(get_lines(left) + newlines(&chunk[..at]))
+            },
+        }
+    }
+
+    // The text of line `line`, without its newline.
+    pub fn line(&mut self, line: usize) -> Option<String> {
+        let start = self.line_to_char(line)?;
+        let end = match self.line_to_char(line + 1) {
+            Some(next) => next - 1,
+            None => self.len_chars(),
+        };
+        self.slice(start..end).ok()
+    }
+}
+
+impl Default for SplayRope {
+    fn default() -> SplayRope {
+        SplayRope::new()
+    }
+}
+
+impl From<&str> for SplayRope {
+    fn from(text: &str) -> SplayRope {
+        let mut rope = SplayRope::new();
+        rope.root = build(text);
+        rope
+    }
+}
+
+impl fmt::Display for SplayRope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (chunk, backwards) in Chunks::new(&self.root) {
+            if backwards {
+                chunk.chars().rev().try_for_each(|c| f.write_char(c))?;
+            } else {
+                f.write_str(chunk)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edits_by_char_offset() {
+        let mut rope = SplayRope::from("héllo wörld");
+        assert_eq!((rope.len_bytes(), rope.len_chars()), (13, 11));
+        rope.insert(5, ",").unwrap();
+        rope.insert(12, "!").unwrap();
+        assert_eq!(rope.insert(14, "?"), Err(IndexError::OutOfBounds { index: 14, len: 13 }));
+        assert_eq!(rope.to_string(), "héllo, wörld!");
+        rope.remove(0..7).unwrap();
+        assert_eq!(rope.slice(1..4), Ok(String::from("örl")));
+        rope.reverse(..).unwrap();
+        assert_eq!(rope.to_string(), "!dlröw");
+        rope.reverse(1..).unwrap();
+        assert_eq!(rope.to_string(), "!wörld");
+        assert_eq!(rope.slice(2..), Ok(String::from("örld")));
+    }
+
+    #[test]
+    fn slices_a_reversed_root_chunk() {
+        // Edits rebuild the root through `join`, so set the flag by hand.
+        let mut rope = SplayRope::new();
+        rope.root = Reversible::reversed(build("abcdef"));
+        assert!(matches!(*rope.root(), RopeNode::Branch { reversed: true, .. }));
+        assert_eq!(rope.to_string(), "fedcba");
+        assert_eq!(rope.slice(1..4).unwrap(), "edc");
+        assert_eq!(rope.slice(..).unwrap(), "fedcba");
+    }
+
+    #[test]
+    fn long_text_spans_chunks() {
+        let text: String = (0..200).map(|i| format!("line {} ✓\n", i)).collect();
+        let mut rope = SplayRope::with_strategy(SplayStrategy::TopDown);
+        rope.insert(0, &text).unwrap();
+        assert_eq!(rope.len_lines(), 201);
+        assert_eq!(rope.line(0), Some(String::from("line 0 ✓")));
+        assert_eq!(rope.line(137), Some(String::from("line 137 ✓")));
+        assert_eq!(rope.line(200), Some(String::new()));
+        assert_eq!(rope.line(201), None);
+        let start = rope.line_to_char(150).unwrap();
+        assert_eq!(rope.char_to_line(start), Ok(150));
+        assert_eq!(rope.char_to_line(start - 1), Ok(149));
+        rope.reverse(start..start + 10).unwrap();
+        assert_eq!(rope.line(150), Some(String::from("✓ 051 enil")));
+        rope.remove(..start).unwrap();
+        assert_eq!(rope.line(0), Some(String::from("✓ 051 enil")));
+        assert_eq!(rope.to_string().len(), rope.len_bytes());
+    }
+
+    #[test]
+    fn typing_fills_chunks() {
+        let mut rope = SplayRope::new();
+        for (i, c) in "a short sentence typed one char at a time".chars().enumerate() {
+            rope.insert(i, &c.to_string()).unwrap();
+        }
+        assert_eq!(rope.to_string(), "a short sentence typed one char at a time");
+        match *rope.root() {
+            RopeNode::Branch { ref left, ref right, .. } => assert!(!TreeCoalgebra::is_branch(&**left) && !TreeCoalgebra::is_branch(&**right)),
+            RopeNode::Empty => panic!("empty rope"),
+        }
+    }
+}
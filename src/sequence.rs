@@ -0,0 +1,437 @@
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{Index, IndexMut, RangeBounds};
+
+use {TreeF, TreeAlgebra, TreeCoalgebra, SubtreeSize, Reversible, SplayStrategy, Count, IndexError};
+use {build_balanced, check_index, check_insert_index, check_range, end, isolate_interval, join, splay, splay_index, zip_tree};
+use stats;
+
+// A sequence of arbitrary values kept in an implicit splay tree, the way
+// `BitRange` keeps bits: nodes are ordered by position rather than by value,
+// and each stores the size of its subtree. Reversing any range takes
+// amortized O(log n), as do indexing, insertion and removal, so it can stand
+// in for a `Vec` wherever reversals are common.
+
+#[derive(Debug)]
+pub enum SequenceNode<A> {
+    Empty,
+    Branch {
+        val: A,
+        size: Count,
+        reversed: bool,
+        left: Box<SequenceNode<A>>,
+        right: Box<SequenceNode<A>>,
+    },
+}
+
+impl<A> TreeAlgebra<A> for SequenceNode<A> {
+    fn combine(input: TreeF<A, SequenceNode<A>>) -> SequenceNode<A> {
+        match input {
+            TreeF::Empty => {
+                SequenceNode::Empty
+            },
+            TreeF::Branch { val, left, right } => {
+                stats::record(|stats| stats.allocations += 2);
+                SequenceNode::Branch {
+                    val: val,
+                    size: Count::new(get_size(&left) + get_size(&right) + 1),
+                    reversed: false,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                }
+            },
+        }
+    }
+}
+
+impl<A> TreeCoalgebra<A> for SequenceNode<A> {
+    fn separate(input: SequenceNode<A>) -> TreeF<A, SequenceNode<A>> {
+        match input {
+            SequenceNode::Empty => {
+                TreeF::Empty
+            },
+            SequenceNode::Branch { val, reversed, left, right, .. } => {
+                if reversed {
+                    TreeF::Branch {
+                        val: val,
+                        left: Reversible::reversed(*right),
+                        right: Reversible::reversed(*left),
+                    }
+                } else {
+                    TreeF::Branch {
+                        val: val,
+                        left: *left,
+                        right: *right,
+                    }
+                }
+            },
+        }
+    }
+    fn is_branch(input: &SequenceNode<A>) -> bool {
+        match *input {
+            SequenceNode::Empty => false,
+            SequenceNode::Branch {..} => true,
+        }
+    }
+}
+
+impl<A> SubtreeSize for SequenceNode<A> {
+    fn subtree_size(input: &SequenceNode<A>) -> usize {
+        match *input {
+            SequenceNode::Empty => 0,
+            SequenceNode::Branch { size, .. } => size.get(),
+        }
+    }
+}
+
+impl<A> Reversible for SequenceNode<A> {
+    fn reversed(input: SequenceNode<A>) -> SequenceNode<A> {
+        match input {
+            SequenceNode::Empty => {
+                SequenceNode::Empty
+            },
+            SequenceNode::Branch { val, size, reversed, left, right } => {
+                SequenceNode::Branch {
+                    val: val,
+                    size: size,
+                    reversed: !reversed,
+                    left: left,
+                    right: right,
+                }
+            },
+        }
+    }
+}
+
+fn get_size<A>(n: &SequenceNode<A>) -> usize {
+    SubtreeSize::subtree_size(n)
+}
+
+fn leaf<A>(val: A) -> SequenceNode<A> {
+    TreeAlgebra::combine(TreeF::Branch {
+        val: val,
+        left: SequenceNode::Empty,
+        right: SequenceNode::Empty,
+    })
+}
+
+// Looks up the element at `index` without restructuring, for the accessors
+// that only get a shared reference. Pending reversals are tracked on the way
+// down.
+fn get_index<A>(root: &SequenceNode<A>, index: usize) -> Option<&A> {
+    let mut node = root;
+    let mut remaining = index;
+    let mut flipped = false;
+    while let SequenceNode::Branch { ref val, reversed, ref left, ref right, .. } = *node {
+        flipped ^= reversed;
+        let (left, right) = if flipped { (right, left) } else { (left, right) };
+        let left_size = get_size(left);
+        match left_size.cmp(&remaining) {
+            Ordering::Less => {
+                remaining = remaining - left_size - 1;
+                node = right;
+            },
+            Ordering::Equal => return Some(val),
+            Ordering::Greater => node = left,
+        }
+    }
+    None
+}
+
+#[derive(Debug)]
+pub struct SplaySequence<A> {
+    root: SequenceNode<A>,
+    strategy: SplayStrategy,
+}
+
+impl<A> SplaySequence<A> {
+    pub fn new() -> SplaySequence<A> {
+        SplaySequence::with_strategy(SplayStrategy::BottomUp)
+    }
+
+    pub fn with_strategy(strategy: SplayStrategy) -> SplaySequence<A> {
+        SplaySequence {
+            root: SequenceNode::Empty,
+            strategy: strategy,
+        }
+    }
+
+    pub fn root(&self) -> &SequenceNode<A> {
+        &self.root
+    }
+
+    pub fn len(&self) -> usize {
+        get_size(&self.root)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Splays the element at `index` to the root and returns it.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut A> {
+        if index >= self.len() {
+            return None;
+        }
+        let old_root = mem::replace(&mut self.root, SequenceNode::Empty);
+        self.root = splay_index(old_root, index, self.strategy);
+        match self.root {
+            SequenceNode::Empty => None,
+            SequenceNode::Branch { ref mut val, .. } => Some(val),
+        }
+    }
+
+    pub fn get(&mut self, index: usize) -> Option<&A> {
+        self.get_mut(index).map(|val| &*val)
+    }
+
+    pub fn first(&mut self) -> Option<&A> {
+        self.get(0)
+    }
+
+    pub fn last(&mut self) -> Option<&A> {
+        match self.len() {
+            0 => None,
+            n => self.get(n - 1),
+        }
+    }
+
+    // Inserts `val` so that it ends up at position `index`, shifting the
+    // following elements up by one. Indices past the end append.
+    pub fn insert(&mut self, index: usize, val: A) {
+        let old_root = mem::replace(&mut self.root, SequenceNode::Empty);
+        let mut zipper = isolate_interval(old_root, index, index, self.strategy);
+        zipper.here = leaf(val);
+        self.root = zip_tree(splay(zipper));
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<A> {
+        if index >= self.len() {
+            return None;
+        }
+        let old_root = mem::replace(&mut self.root, SequenceNode::Empty);
+        match TreeCoalgebra::separate(splay_index(old_root, index, self.strategy)) {
+            TreeF::Empty => None,
+            TreeF::Branch { val, left, right } => {
+                self.root = join(left, right);
+                Some(val)
+            },
+        }
+    }
+
+    pub fn push(&mut self, val: A) {
+        let old_root = mem::replace(&mut self.root, SequenceNode::Empty);
+        let mut zipper = end(old_root);
+        zipper.here = leaf(val);
+        self.root = zip_tree(splay(zipper));
+    }
+
+    pub fn pop(&mut self) -> Option<A> {
+        match self.len() {
+            0 => None,
+            n => self.remove(n - 1),
+        }
+    }
+
+    // Inserting at the length appends.
+    pub fn try_insert(&mut self, index: usize, val: A) -> Result<(), IndexError> {
+        check_insert_index(index, self.len())?;
+        self.insert(index, val);
+        Ok(())
+    }
+
+    pub fn reverse<R: RangeBounds<usize>>(&mut self, range: R) -> Result<(), IndexError> {
+        let (start, end) = check_range(range, self.len())?;
+        let old_root = mem::replace(&mut self.root, SequenceNode::Empty);
+        let mut zipper = isolate_interval(old_root, start, end, self.strategy);
+        zipper.here = Reversible::reversed(zipper.here);
+        self.root = zip_tree(zipper);
+        Ok(())
+    }
+
+    // Exchanges the elements at `a` and `b`. The later one is splayed to the
+    // root and the earlier one to the root of its left subtree, where the two
+    // values can be swapped in place.
+    pub fn swap(&mut self, a: usize, b: usize) -> Result<(), IndexError> {
+        check_index(a, self.len())?;
+        check_index(b, self.len())?;
+        let (a, b) = if a < b { (a, b) } else { (b, a) };
+        if a == b {
+            return Ok(());
+        }
+        let old_root = mem::replace(&mut self.root, SequenceNode::Empty);
+        if let TreeF::Branch { val, left, right } = TreeCoalgebra::separate(splay_index(old_root, b, self.strategy)) {
+            if let TreeF::Branch { val: left_val, left: left_left, right: left_right } =
+                TreeCoalgebra::separate(splay_index(left, a, self.strategy)) {
+                self.root = TreeAlgebra::combine(TreeF::Branch {
+                    val: left_val,
+                    left: TreeAlgebra::combine(TreeF::Branch {
+                        val: val,
+                        left: left_left,
+                        right: left_right,
+                    }),
+                    right: right,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn position<P: FnMut(&A) -> bool>(&self, pred: P) -> Option<usize> {
+        self.iter().position(pred)
+    }
+
+    pub fn contains(&self, x: &A) -> bool where A: PartialEq {
+        self.iter().any(|val| val == x)
+    }
+
+    pub fn iter(&self) -> Iter<'_, A> {
+        let mut iter = Iter {
+            stack: Vec::new(),
+            remaining: self.len(),
+        };
+        iter.descend(&self.root, false);
+        iter
+    }
+
+    pub fn to_vec(&self) -> Vec<A> where A: Clone {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<A> Default for SplaySequence<A> {
+    fn default() -> SplaySequence<A> {
+        SplaySequence::new()
+    }
+}
+
+// Indexing walks down without splaying, as it only has a shared reference;
+// `get` is the amortized O(log n) lookup.
+impl<A> Index<usize> for SplaySequence<A> {
+    type Output = A;
+
+    fn index(&self, index: usize) -> &A {
+        match get_index(&self.root, index) {
+            Some(val) => val,
+            None => panic!("index {} is out of bounds for length {}", index, self.len()),
+        }
+    }
+}
+
+impl<A> IndexMut<usize> for SplaySequence<A> {
+    fn index_mut(&mut self, index: usize) -> &mut A {
+        let len = self.len();
+        match self.get_mut(index) {
+            Some(val) => val,
+            None => panic!("index {} is out of bounds for length {}", index, len),
+        }
+    }
+}
+
+// Appended elements are built into a balanced tree first and joined on in
+// one go.
+impl<A> Extend<A> for SplaySequence<A> {
+    fn extend<I: IntoIterator<Item = A>>(&mut self, iter: I) {
+        let vals: Vec<A> = iter.into_iter().collect();
+        let n = vals.len();
+        let mut vals = vals.into_iter();
+        let result: Result<SequenceNode<A>, ()> = build_balanced(n, &mut || Ok(vals.next().unwrap()));
+        let old_root = mem::replace(&mut self.root, SequenceNode::Empty);
+        self.root = join(old_root, result.unwrap());
+    }
+}
+
+impl<A> FromIterator<A> for SplaySequence<A> {
+    fn from_iter<I: IntoIterator<Item = A>>(iter: I) -> SplaySequence<A> {
+        let mut sequence = SplaySequence::new();
+        sequence.extend(iter);
+        sequence
+    }
+}
+
+// An in-order walk of the stored tree. The stack holds, for each node whose
+// left side is still being visited, its value, the subtree to visit after it
+// and whether that subtree's children are reversed.
+pub struct Iter<'a, A: 'a> {
+    stack: Vec<(&'a A, &'a SequenceNode<A>, bool)>,
+    remaining: usize,
+}
+
+impl<'a, A> Iter<'a, A> {
+    fn descend(&mut self, node: &'a SequenceNode<A>, flipped: bool) {
+        let mut node = node;
+        let mut flipped = flipped;
+        while let SequenceNode::Branch { ref val, reversed, ref left, ref right, .. } = *node {
+            flipped ^= reversed;
+            let (first, second) = if flipped { (right, left) } else { (left, right) };
+            self.stack.push((val, &**second, flipped));
+            node = first;
+        }
+    }
+}
+
+impl<'a, A> Iterator for Iter<'a, A> {
+    type Item = &'a A;
+
+    fn next(&mut self) -> Option<&'a A> {
+        let (val, next, flipped) = self.stack.pop()?;
+        self.descend(next, flipped);
+        self.remaining -= 1;
+        Some(val)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, A> ExactSizeIterator for Iter<'a, A> {}
+
+impl<'a, A> IntoIterator for &'a SplaySequence<A> {
+    type Item = &'a A;
+    type IntoIter = Iter<'a, A>;
+
+    fn into_iter(self) -> Iter<'a, A> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn behaves_like_a_vec() {
+        let mut sequence: SplaySequence<i32> = (0..10).collect();
+        assert_eq!(sequence.len(), 10);
+        assert_eq!(sequence.first(), Some(&0));
+        assert_eq!(sequence.last(), Some(&9));
+        sequence.reverse(2..8).unwrap();
+        assert_eq!(sequence.to_vec(), vec![0, 1, 7, 6, 5, 4, 3, 2, 8, 9]);
+        sequence.swap(0, 9).unwrap();
+        sequence.swap(3, 3).unwrap();
+        assert_eq!(sequence.swap(1, 10), Err(IndexError::OutOfBounds { index: 10, len: 10 }));
+        assert_eq!(sequence.try_insert(11, 0), Err(IndexError::OutOfBounds { index: 11, len: 10 }));
+        sequence[4] += 100;
+        assert_eq!(sequence[4], 105);
+        assert_eq!(sequence.position(|&x| x > 100), Some(4));
+        assert!(sequence.contains(&8) && !sequence.contains(&5));
+        sequence.extend(vec![20, 21]);
+        sequence.insert(1, -1);
+        assert_eq!(sequence.remove(0), Some(9));
+        assert_eq!(sequence.pop(), Some(21));
+        assert_eq!(sequence.iter().len(), 11);
+        assert_eq!(sequence.to_vec(), vec![-1, 1, 7, 6, 105, 4, 3, 2, 8, 0, 20]);
+    }
+
+    #[test]
+    fn empty_sequence() {
+        let mut sequence: SplaySequence<String> = SplaySequence::default();
+        assert!(sequence.is_empty());
+        assert_eq!(sequence.first(), None);
+        assert_eq!(sequence.pop(), None);
+        assert_eq!(sequence.reverse(..), Ok(()));
+        assert_eq!(sequence.iter().next(), None);
+    }
+}
@@ -0,0 +1,425 @@
+use std::fmt;
+use std::marker::PhantomData;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+use {TreeF, TreeNode, BitOp, BitRangeNode, Count, Splay, SplayTree, BitRange};
+use build_balanced;
+
+// Serde support, enabled with the `serde` feature.
+//
+// By default only the contents are kept: a splay tree is written as its values
+// in increasing order and a bit range as its length and bits packed eight to a
+// byte, lowest index in the lowest bit. Reading either back builds a balanced
+// tree with the default strategy and policy. The shape a tree was splayed
+// into is usually of no interest, but when reproducing a problem it can be,
+// so wrapping a tree in `Shaped` writes and reads every node instead,
+// including the reversals and updates a bit range still owes its children:
+//
+//     let json = serde_json::to_string(&Shaped(&tree))?;
+//     let Shaped(tree): Shaped<SplayTree<i32>> = serde_json::from_str(&json)?;
+//
+// Shaped trees are written as a flat sequence of nodes in post-order, each
+// followed by whether it has a left and a right child, so that deep trees
+// need no recursion on either side.
+
+pub struct Shaped<T>(pub T);
+
+// Collects the branches of a tree in post-order. `children` returns None for
+// empty nodes.
+fn post_order<'a, N, F>(root: &'a N, children: F) -> Vec<&'a N>
+    where F: Fn(&'a N) -> Option<(&'a N, &'a N)> {
+    let mut nodes = Vec::new();
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        if let Some((left, right)) = children(node) {
+            nodes.push(node);
+            stack.push(left);
+            stack.push(right);
+        }
+    }
+    // That was node, right subtree, left subtree; reversed it is post-order.
+    nodes.reverse();
+    nodes
+}
+
+fn tree_children<A>(node: &TreeNode<A>) -> Option<(&TreeNode<A>, &TreeNode<A>)> {
+    match *node {
+        TreeNode(TreeF::Empty) => None,
+        TreeNode(TreeF::Branch { ref left, ref right, .. }) => Some((&**left, &**right)),
+    }
+}
+
+fn bit_range_children(node: &BitRangeNode) -> Option<(&BitRangeNode, &BitRangeNode)> {
+    match *node {
+        BitRangeNode::Empty => None,
+        BitRangeNode::Branch { ref left, ref right, .. } => Some((&**left, &**right)),
+    }
+}
+
+// Rebuilds a tree from nodes in post-order. `make` receives each node with
+// its left and right child.
+fn rebuild<'de, S, T, N, F, G>(mut seq: S, empty: F, mut make: G) -> Result<N, S::Error>
+    where S: SeqAccess<'de>, T: Deserialize<'de> + HasChildren, F: Fn() -> N, G: FnMut(T, N, N) -> N {
+    let mut stack: Vec<N> = Vec::new();
+    while let Some(entry) = seq.next_element::<T>()? {
+        let (has_left, has_right) = entry.children();
+        let right = if has_right { stack.pop() } else { Some(empty()) };
+        let left = if has_left { stack.pop() } else { Some(empty()) };
+        match (left, right) {
+            (Some(left), Some(right)) => stack.push(make(entry, left, right)),
+            _ => return Err(de::Error::custom("node refers to a missing child")),
+        }
+    }
+    match stack.len() {
+        0 => Ok(empty()),
+        1 => Ok(stack.pop().unwrap()),
+        _ => Err(de::Error::custom("nodes do not form a single tree")),
+    }
+}
+
+trait HasChildren {
+    fn children(&self) -> (bool, bool);
+}
+
+impl<A> HasChildren for (A, bool, bool) {
+    fn children(&self) -> (bool, bool) {
+        (self.1, self.2)
+    }
+}
+
+impl HasChildren for (bool, bool, u8, bool, bool) {
+    fn children(&self) -> (bool, bool) {
+        (self.3, self.4)
+    }
+}
+
+impl<A: Serialize> Serialize for SplayTree<A> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut values = Vec::new();
+        let mut stack = Vec::new();
+        let mut node = &self.root;
+        loop {
+            while let Some((left, _)) = tree_children(node) {
+                stack.push(node);
+                node = left;
+            }
+            match stack.pop() {
+                None => break,
+                Some(parent) => {
+                    values.push(parent);
+                    node = tree_children(parent).unwrap().1;
+                },
+            }
+        }
+        serializer.collect_seq(values.into_iter().map(|node| match *node {
+            TreeNode(TreeF::Branch { ref val, .. }) => val,
+            TreeNode(TreeF::Empty) => unreachable!(),
+        }))
+    }
+}
+
+impl<'de, A: Ord + Deserialize<'de>> Deserialize<'de> for SplayTree<A> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SplayTree<A>, D::Error> {
+        let values = Vec::<A>::deserialize(deserializer)?;
+        if values.windows(2).any(|pair| pair[0] >= pair[1]) {
+            return Err(de::Error::custom("values are not in strictly increasing order"));
+        }
+        let n = values.len();
+        let mut values = values.into_iter();
+        let mut tree = SplayTree::new();
+        tree.root = build_balanced(n, &mut || values.next().ok_or(de::Error::custom("missing value")))?;
+        Ok(tree)
+    }
+}
+
+impl<A: Serialize> Serialize for Shaped<&SplayTree<A>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(post_order(&self.0.root, tree_children).into_iter().map(|node| match *node {
+            TreeNode(TreeF::Branch { ref val, ref left, ref right }) => {
+                (val, tree_children(left).is_some(), tree_children(right).is_some())
+            },
+            TreeNode(TreeF::Empty) => unreachable!(),
+        }))
+    }
+}
+
+struct ShapedTreeVisitor<A>(PhantomData<A>);
+
+impl<'de, A: Deserialize<'de>> Visitor<'de> for ShapedTreeVisitor<A> {
+    type Value = TreeNode<A>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of tree nodes in post-order")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, seq: S) -> Result<TreeNode<A>, S::Error> {
+        rebuild(seq, || TreeNode(TreeF::Empty), |(val, _, _): (A, bool, bool), left, right| {
+            TreeNode(TreeF::Branch {
+                val: val,
+                left: Box::new(left),
+                right: Box::new(right),
+            })
+        })
+    }
+}
+
+impl<'de, A: Ord + Deserialize<'de>> Deserialize<'de> for Shaped<SplayTree<A>> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Shaped<SplayTree<A>>, D::Error> {
+        let mut tree = SplayTree::new();
+        tree.root = deserializer.deserialize_seq(ShapedTreeVisitor(PhantomData))?;
+        tree.validate().map_err(de::Error::custom)?;
+        Ok(Shaped(tree))
+    }
+}
+
+struct PackedBits<'a>(&'a [u8]);
+
+impl<'a> Serialize for PackedBits<'a> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.0)
+    }
+}
+
+// Accepts bytes however the format hands them over; self-describing formats
+// such as JSON write them as a sequence of numbers.
+struct ByteBuf(Vec<u8>);
+
+struct ByteBufVisitor;
+
+impl<'de> Visitor<'de> for ByteBufVisitor {
+    type Value = ByteBuf;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("packed bits")
+    }
+
+    fn visit_bytes<E: de::Error>(self, bytes: &[u8]) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(bytes.to_vec()))
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, bytes: Vec<u8>) -> Result<ByteBuf, E> {
+        Ok(ByteBuf(bytes))
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<ByteBuf, S::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(ByteBuf(bytes))
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ByteBuf, D::Error> {
+        deserializer.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+impl Serialize for BitRange {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bits = self.to_vec();
+        let mut packed = vec![0u8; bits.len().div_ceil(8)];
+        for (i, &bit) in bits.iter().enumerate() {
+            packed[i / 8] |= (bit as u8) << (i % 8);
+        }
+        let mut state = serializer.serialize_struct("BitRange", 2)?;
+        state.serialize_field("len", &(bits.len() as u64))?;
+        state.serialize_field("bits", &PackedBits(&packed))?;
+        state.end()
+    }
+}
+
+const FIELDS: &[&str] = &["len", "bits"];
+
+struct BitRangeVisitor;
+
+impl BitRangeVisitor {
+    fn build<E: de::Error>(len: u64, bits: ByteBuf) -> Result<BitRange, E> {
+        if len > Count::MAX.min(usize::MAX as u64) {
+            return Err(E::custom(format!("length {} is too large", len)));
+        }
+        let len = len as usize;
+        if bits.0.len() != len.div_ceil(8) {
+            return Err(E::invalid_length(bits.0.len(), &"one byte for every eight bits"));
+        }
+        let mut index = 0;
+        let mut range = BitRange::new(0);
+        range.root = build_balanced(len, &mut || {
+            let bit = bits.0[index / 8] >> (index % 8) & 1 == 1;
+            index += 1;
+            Ok::<bool, E>(bit)
+        })?;
+        Ok(range)
+    }
+}
+
+impl<'de> Visitor<'de> for BitRangeVisitor {
+    type Value = BitRange;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a bit range")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, mut seq: S) -> Result<BitRange, S::Error> {
+        let len = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let bits = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        BitRangeVisitor::build(len, bits)
+    }
+
+    fn visit_map<M: MapAccess<'de>>(self, mut map: M) -> Result<BitRange, M::Error> {
+        let (mut len, mut bits) = (None, None);
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "len" if len.is_none() => len = Some(map.next_value()?),
+                "bits" if bits.is_none() => bits = Some(map.next_value()?),
+                "len" | "bits" => return Err(de::Error::custom(format!("duplicate field `{}`", key))),
+                _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+            }
+        }
+        let len = len.ok_or_else(|| de::Error::missing_field("len"))?;
+        let bits = bits.ok_or_else(|| de::Error::missing_field("bits"))?;
+        BitRangeVisitor::build(len, bits)
+    }
+}
+
+impl<'de> Deserialize<'de> for BitRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BitRange, D::Error> {
+        deserializer.deserialize_struct("BitRange", FIELDS, BitRangeVisitor)
+    }
+}
+
+// Each node is written as its stored bit, whether its children are reversed,
+// the update they are owed and whether it has each child. Sizes and counts
+// follow from those.
+impl Serialize for Shaped<&BitRange> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(post_order(&self.0.root, bit_range_children).into_iter().map(|node| match *node {
+            BitRangeNode::Branch { here, reversed, pending, ref left, ref right, .. } => {
+                let (has_left, has_right) = (bit_range_children(left).is_some(), bit_range_children(right).is_some());
+                (here, reversed, pending.code(), has_left, has_right)
+            },
+            BitRangeNode::Empty => unreachable!(),
+        }))
+    }
+}
+
+struct ShapedBitsVisitor;
+
+fn size_and_ones(node: &BitRangeNode) -> (usize, usize) {
+    match *node {
+        BitRangeNode::Empty => (0, 0),
+        BitRangeNode::Branch { size, ones, .. } => (size.get(), ones.get()),
+    }
+}
+
+impl<'de> Visitor<'de> for ShapedBitsVisitor {
+    type Value = BitRangeNode;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of bit range nodes in post-order")
+    }
+
+    fn visit_seq<S: SeqAccess<'de>>(self, seq: S) -> Result<BitRangeNode, S::Error> {
+        let mut invalid = None;
+        let root = rebuild(seq, || BitRangeNode::Empty, |(here, reversed, code, _, _), left, right| {
+            let pending = BitOp::from_code(code).unwrap_or_else(|| {
+                invalid = Some(code);
+                BitOp::Keep
+            });
+            let (left_size, left_ones) = size_and_ones(&left);
+            let (right_size, right_ones) = size_and_ones(&right);
+            BitRangeNode::Branch {
+                here: here,
+                size: Count::new(left_size + right_size + 1),
+                ones: Count::new(pending.ones(left_ones + right_ones, left_size + right_size) + here as usize),
+                reversed: reversed,
+                pending: pending,
+                left: Box::new(left),
+                right: Box::new(right),
+            }
+        })?;
+        match invalid {
+            Some(code) => Err(de::Error::custom(format!("invalid pending operation {}", code))),
+            None => Ok(root),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Shaped<BitRange> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Shaped<BitRange>, D::Error> {
+        let mut range = BitRange::new(0);
+        range.root = deserializer.deserialize_seq(ShapedBitsVisitor)?;
+        Ok(Shaped(range))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+
+    use {SplayStrategy, Splay, SplayTree, BitRange};
+    use super::*;
+
+    fn sample_tree() -> SplayTree<i32> {
+        let mut tree = SplayTree::with_strategy(SplayStrategy::BottomUp);
+        for &v in &[5, 1, 9, 3, 7, 2, 8] {
+            tree.insert(v);
+        }
+        tree.contains(3);
+        tree
+    }
+
+    fn sample_range() -> BitRange {
+        let mut range = BitRange::new(11);
+        range.set(0, true);
+        range.set(4, true);
+        range.flip_range(2, 9);
+        range.reverse_range(1, 10);
+        range
+    }
+
+    #[test]
+    fn trees_round_trip_as_sorted_values() {
+        let json = serde_json::to_string(&sample_tree()).unwrap();
+        assert_eq!(json, "[1,2,3,5,7,8,9]");
+        let mut tree: SplayTree<i32> = serde_json::from_str(&json).unwrap();
+        assert!(tree.validate().is_ok());
+        assert!(tree.contains(7) && !tree.contains(4));
+        assert!(serde_json::from_str::<SplayTree<i32>>("[1,3,3]").is_err());
+    }
+
+    #[test]
+    fn shaped_trees_keep_their_shape() {
+        let tree = sample_tree();
+        let json = serde_json::to_string(&Shaped(&tree)).unwrap();
+        let Shaped(copy): Shaped<SplayTree<i32>> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", copy.root()), format!("{:?}", tree.root()));
+        assert!(serde_json::from_str::<Shaped<SplayTree<i32>>>("[[1,false,false],[2,false,false]]").is_err());
+        assert!(serde_json::from_str::<Shaped<SplayTree<i32>>>("[[2,false,false],[1,true,false]]").is_err());
+        assert!(serde_json::from_str::<Shaped<SplayTree<i32>>>("[[1,false,true]]").is_err());
+    }
+
+    #[test]
+    fn bit_ranges_round_trip_as_packed_bits() {
+        let range = sample_range();
+        let json = serde_json::to_string(&range).unwrap();
+        assert_eq!(json, r#"{"len":11,"bits":[189,1]}"#);
+        let copy: BitRange = serde_json::from_str(&json).unwrap();
+        assert_eq!(copy.to_vec(), range.to_vec());
+        assert!(copy.validate().is_ok());
+        assert!(serde_json::from_str::<BitRange>(r#"{"len":17,"bits":[1,2]}"#).is_err());
+        assert_eq!(serde_json::from_str::<BitRange>("[0,[]]").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn shaped_bit_ranges_keep_pending_work() {
+        let range = sample_range();
+        let json = serde_json::to_string(&Shaped(&range)).unwrap();
+        let Shaped(copy): Shaped<BitRange> = serde_json::from_str(&json).unwrap();
+        assert_eq!(format!("{:?}", copy.root()), format!("{:?}", range.root()));
+        assert!(serde_json::from_str::<Shaped<BitRange>>("[[true,false,7,false,false]]").is_err());
+    }
+}
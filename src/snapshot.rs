@@ -0,0 +1,324 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufWriter, Read, Write};
+
+use {BitOp, BitRange, BitRangeNode, Count};
+use build_balanced;
+
+// A binary checkpoint format for bit ranges. A snapshot starts with a header:
+//
+//     magic     8 bytes  "SPLAYBIT"
+//     version   1 byte   currently 1
+//     flags     1 byte   bit 0 set if the tree's shape follows
+//     length    8 bytes  number of bits, little-endian
+//
+// Without the shape, the bits follow packed eight to a byte, lowest index in
+// the lowest bit, and reading them back builds a balanced tree. With it, one
+// byte per node follows in post-order, holding the node's stored bit (bit 0),
+// whether its children are reversed (bit 1), the code of the update they are
+// still owed (bits 2 and 3) and whether it has a left and a right child (bits
+// 4 and 5), so the exact tree, pending work and all, can be restored.
+//
+// Both directions stream: nothing the size of the sequence is built besides
+// the tree itself, and reading stops at the end of the snapshot, so it can
+// be followed by other data.
+
+const MAGIC: &[u8; 8] = b"SPLAYBIT";
+const VERSION: u8 = 1;
+const SHAPE_FLAG: u8 = 1;
+
+const HERE: u8 = 1;
+const REVERSED: u8 = 1 << 1;
+const PENDING_SHIFT: u8 = 2;
+const HAS_LEFT: u8 = 1 << 4;
+const HAS_RIGHT: u8 = 1 << 5;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnknownFlags(u8),
+    TooLong(u64),
+    InvalidShape,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnapshotError::Io(ref e) => write!(f, "{}", e),
+            SnapshotError::BadMagic => write!(f, "not a bit range snapshot"),
+            SnapshotError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+            SnapshotError::UnknownFlags(flags) => write!(f, "unknown snapshot flags {:#04x}", flags),
+            SnapshotError::TooLong(len) => write!(f, "snapshot of {} bits is too long", len),
+            SnapshotError::InvalidShape => write!(f, "snapshot nodes do not form a tree"),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SnapshotError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> SnapshotError {
+        SnapshotError::Io(e)
+    }
+}
+
+// Reads exactly the bytes a snapshot says it has, a block at a time.
+struct Chunks<R> {
+    input: R,
+    buf: Vec<u8>,
+    pos: usize,
+    remaining: u64,
+}
+
+impl<R: Read> Chunks<R> {
+    fn new(input: R, remaining: u64) -> Chunks<R> {
+        Chunks {
+            input: input,
+            buf: Vec::new(),
+            pos: 0,
+            remaining: remaining,
+        }
+    }
+
+    fn next(&mut self) -> io::Result<u8> {
+        if self.pos == self.buf.len() {
+            let block = self.remaining.min(8192) as usize;
+            if block == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "read past the end of the snapshot"));
+            }
+            self.buf.resize(block, 0);
+            self.input.read_exact(&mut self.buf)?;
+            self.remaining -= block as u64;
+            self.pos = 0;
+        }
+        self.pos += 1;
+        Ok(self.buf[self.pos - 1])
+    }
+}
+
+fn node_byte(node: &BitRangeNode) -> u8 {
+    match *node {
+        BitRangeNode::Empty => 0,
+        BitRangeNode::Branch { here, reversed, pending, ref left, ref right, .. } => {
+            let mut byte = here as u8 | pending.code() << PENDING_SHIFT;
+            if reversed {
+                byte |= REVERSED;
+            }
+            if let BitRangeNode::Branch { .. } = **left {
+                byte |= HAS_LEFT;
+            }
+            if let BitRangeNode::Branch { .. } = **right {
+                byte |= HAS_RIGHT;
+            }
+            byte
+        },
+    }
+}
+
+fn size_and_ones(node: &BitRangeNode) -> (usize, usize) {
+    match *node {
+        BitRangeNode::Empty => (0, 0),
+        BitRangeNode::Branch { size, ones, .. } => (size.get(), ones.get()),
+    }
+}
+
+impl BitRange {
+    // Writes the bits, packed.
+    pub fn write_to<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut out = BufWriter::new(out);
+        write_header(&mut out, 0, self.len() as u64)?;
+        let (mut byte, mut filled) = (0u8, 0);
+        self.try_for_each(|bit| {
+            byte |= (bit as u8) << filled;
+            filled += 1;
+            if filled == 8 {
+                out.write_all(&[byte])?;
+                byte = 0;
+                filled = 0;
+            }
+            Ok::<(), io::Error>(())
+        })?;
+        if filled > 0 {
+            out.write_all(&[byte])?;
+        }
+        out.flush()
+    }
+
+    // Writes every node as it is stored, for reproducing a tree exactly.
+    pub fn write_shaped_to<W: Write>(&self, out: W) -> io::Result<()> {
+        let mut out = BufWriter::new(out);
+        write_header(&mut out, SHAPE_FLAG, self.len() as u64)?;
+        // A post-order walk that keeps only the current path, with a flag
+        // for whether each node's children have been visited.
+        let mut stack = vec![(&self.root, false)];
+        while let Some((node, visited)) = stack.pop() {
+            if let BitRangeNode::Branch { ref left, ref right, .. } = *node {
+                if visited {
+                    out.write_all(&[node_byte(node)])?;
+                } else {
+                    stack.push((node, true));
+                    stack.push((&**right, false));
+                    stack.push((&**left, false));
+                }
+            }
+        }
+        out.flush()
+    }
+
+    // Reads a snapshot written by either method. The strategy and policy are
+    // the defaults.
+    pub fn read_from<R: Read>(mut input: R) -> Result<BitRange, SnapshotError> {
+        let mut header = [0u8; 18];
+        input.read_exact(&mut header)?;
+        if header[..8] != MAGIC[..] {
+            return Err(SnapshotError::BadMagic);
+        }
+        if header[8] != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header[8]));
+        }
+        let flags = header[9];
+        if flags & !SHAPE_FLAG != 0 {
+            return Err(SnapshotError::UnknownFlags(flags));
+        }
+        let mut len_bytes = [0u8; 8];
+        len_bytes.copy_from_slice(&header[10..]);
+        let len = u64::from_le_bytes(len_bytes);
+        if len > Count::MAX.min(usize::MAX as u64) {
+            return Err(SnapshotError::TooLong(len));
+        }
+        let mut range = BitRange::new(0);
+        range.root = if flags & SHAPE_FLAG != 0 {
+            read_shape(Chunks::new(input, len), len as usize)?
+        } else {
+            let mut bytes = Chunks::new(input, len.div_ceil(8));
+            let (mut byte, mut left) = (0u8, 0);
+            build_balanced(len as usize, &mut || {
+                if left == 0 {
+                    byte = bytes.next()?;
+                    left = 8;
+                }
+                let bit = byte & 1 == 1;
+                byte >>= 1;
+                left -= 1;
+                Ok::<bool, io::Error>(bit)
+            })?
+        };
+        Ok(range)
+    }
+}
+
+fn write_header<W: Write>(out: &mut W, flags: u8, len: u64) -> io::Result<()> {
+    out.write_all(MAGIC)?;
+    out.write_all(&[VERSION, flags])?;
+    out.write_all(&len.to_le_bytes())
+}
+
+fn read_shape<R: Read>(mut bytes: Chunks<R>, len: usize) -> Result<BitRangeNode, SnapshotError> {
+    // Completed subtrees waiting for their parent.
+    let mut stack: Vec<BitRangeNode> = Vec::new();
+    for _ in 0..len {
+        let byte = bytes.next()?;
+        let pending = BitOp::from_code(byte >> PENDING_SHIFT & 3).unwrap();
+        if byte >> 6 != 0 {
+            return Err(SnapshotError::InvalidShape);
+        }
+        let mut child = |present: bool| {
+            if present { stack.pop().ok_or(SnapshotError::InvalidShape) } else { Ok(BitRangeNode::Empty) }
+        };
+        let right = child(byte & HAS_RIGHT != 0)?;
+        let left = child(byte & HAS_LEFT != 0)?;
+        let (left_size, left_ones) = size_and_ones(&left);
+        let (right_size, right_ones) = size_and_ones(&right);
+        let here = byte & HERE != 0;
+        stack.push(BitRangeNode::Branch {
+            here: here,
+            size: Count::new(left_size + right_size + 1),
+            ones: Count::new(pending.ones(left_ones + right_ones, left_size + right_size) + here as usize),
+            reversed: byte & REVERSED != 0,
+            pending: pending,
+            left: Box::new(left),
+            right: Box::new(right),
+        });
+    }
+    match stack.len() {
+        0 => Ok(BitRangeNode::Empty),
+        1 => Ok(stack.pop().unwrap()),
+        _ => Err(SnapshotError::InvalidShape),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use BitRange;
+    use super::*;
+
+    fn sample() -> BitRange {
+        let mut range = BitRange::new(21);
+        range.set(0, true);
+        range.set(4, true);
+        range.fill_range(12, 20, true);
+        range.flip_range(2, 15);
+        range.reverse_range(1, 19);
+        range
+    }
+
+    #[test]
+    fn round_trips_packed_bits() {
+        let range = sample();
+        let mut bytes = Vec::new();
+        range.write_to(&mut bytes).unwrap();
+        assert_eq!(&bytes[..10], b"SPLAYBIT\x01\x00");
+        assert_eq!(bytes.len(), 18 + 3);
+        // Trailing data is left alone.
+        bytes.extend_from_slice(b"rest");
+        let mut input = &bytes[..];
+        let copy = BitRange::read_from(&mut input).unwrap();
+        assert_eq!(input, b"rest");
+        assert_eq!(copy.to_vec(), range.to_vec());
+        assert!(copy.validate().is_ok());
+    }
+
+    #[test]
+    fn round_trips_shape() {
+        let range = sample();
+        let mut bytes = Vec::new();
+        range.write_shaped_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), 18 + 21);
+        let copy = BitRange::read_from(&bytes[..]).unwrap();
+        assert_eq!(format!("{:?}", copy.root()), format!("{:?}", range.root()));
+
+        let mut empty = Vec::new();
+        BitRange::new(0).write_shaped_to(&mut empty).unwrap();
+        assert!(BitRange::read_from(&empty[..]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_bad_snapshots() {
+        let mut bytes = Vec::new();
+        sample().write_shaped_to(&mut bytes).unwrap();
+        let corrupt = |at: usize, byte: u8| {
+            let mut bytes = bytes.clone();
+            bytes[at] = byte;
+            BitRange::read_from(&bytes[..]).unwrap_err()
+        };
+        assert!(matches!(corrupt(0, b'X'), SnapshotError::BadMagic));
+        assert!(matches!(corrupt(8, 2), SnapshotError::UnsupportedVersion(2)));
+        assert!(matches!(corrupt(9, 3), SnapshotError::UnknownFlags(3)));
+        assert!(matches!(corrupt(17, 1), SnapshotError::TooLong(_)));
+        // The first node in post-order is a leaf; give it a child.
+        assert!(matches!(corrupt(18, HAS_LEFT), SnapshotError::InvalidShape));
+        match BitRange::read_from(&bytes[..bytes.len() - 1]).unwrap_err() {
+            SnapshotError::Io(e) => assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
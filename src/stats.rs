@@ -0,0 +1,131 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+// Counters for judging how well splaying suits a workload. The tree
+// operations themselves are free functions over any tree type, so rather than
+// threading a collector through every one of them, a tree with statistics
+// enabled installs its counters in a thread-local slot for the duration of
+// each public operation and takes them back afterwards. When no counters are
+// installed, recording is a no-op: the hooks sit on the hottest paths of
+// every tree in the crate, so they check a plain flag before going near the
+// `RefCell`.
+//
+// A zig is a single rotation, a zig-zig or zig-zag a double one. Top-down
+// splaying links nodes instead of rotating them; each link is counted as a
+// zig and each link that rotates first as a zig-zig. Every descent from the
+// root counts as an access, and its length is the number of edges followed.
+// Allocations count the boxes the tree builds.
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub operations: u64,
+    pub zigs: u64,
+    pub zig_zigs: u64,
+    pub zig_zags: u64,
+    pub accesses: u64,
+    pub path_length: u64,
+    pub max_depth: usize,
+    pub allocations: u64,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats::default()
+    }
+
+    pub fn rotations(&self) -> u64 {
+        self.zigs + 2 * (self.zig_zigs + self.zig_zags)
+    }
+
+    // The average number of rotations per operation, which is what the
+    // amortized O(log n) bound of splaying is about.
+    pub fn rotations_per_operation(&self) -> f64 {
+        if self.operations == 0 { 0.0 } else { self.rotations() as f64 / self.operations as f64 }
+    }
+
+    pub fn mean_path_length(&self) -> f64 {
+        if self.accesses == 0 { 0.0 } else { self.path_length as f64 / self.accesses as f64 }
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "operations: {}", self.operations)?;
+        writeln!(f, "zig: {}, zig-zig: {}, zig-zag: {}", self.zigs, self.zig_zigs, self.zig_zags)?;
+        writeln!(f, "rotations: {} ({:.2} per operation)", self.rotations(), self.rotations_per_operation())?;
+        writeln!(f, "accesses: {}, mean path length: {:.2}, max depth: {}",
+                 self.accesses, self.mean_path_length(), self.max_depth)?;
+        write!(f, "allocations: {}", self.allocations)
+    }
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<Stats>> = const { RefCell::new(None) };
+    // Whether ACTIVE holds any counters.
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+}
+
+#[inline]
+pub(crate) fn record<F: FnOnce(&mut Stats)>(f: F) {
+    if !RECORDING.with(Cell::get) {
+        return;
+    }
+    ACTIVE.with(|active| {
+        if let Some(ref mut stats) = *active.borrow_mut() {
+            f(stats);
+        }
+    })
+}
+
+#[inline]
+pub(crate) fn record_path(depth: usize) {
+    record(|stats| {
+        stats.accesses += 1;
+        stats.path_length += depth as u64;
+        stats.max_depth = stats.max_depth.max(depth);
+    })
+}
+
+// Counters installed for the current operation. Dropping it uninstalls them,
+// so that an operation that panics does not leave them behind to collect
+// every later operation on the thread.
+struct Installed;
+
+impl Installed {
+    fn new(stats: Stats) -> Installed {
+        ACTIVE.with(|active| *active.borrow_mut() = Some(stats));
+        RECORDING.with(|recording| recording.set(true));
+        Installed
+    }
+
+    fn finish(self) -> Option<Stats> {
+        RECORDING.with(|recording| recording.set(false));
+        ACTIVE.with(|active| active.borrow_mut().take())
+    }
+}
+
+impl Drop for Installed {
+    fn drop(&mut self) {
+        RECORDING.with(|recording| recording.set(false));
+        ACTIVE.with(|active| active.borrow_mut().take());
+    }
+}
+
+// Runs one operation with `stats` installed, if there are any, and hands
+// them back updated. Operations called from inside another are counted as
+// part of the outer one. Callers pass a copy and keep their own until this
+// returns, so a panic leaves them with the counts from before the operation.
+pub(crate) fn collect<R, F: FnOnce() -> R>(stats: Option<Stats>, f: F) -> (R, Option<Stats>) {
+    let mut stats = match stats {
+        None => return (f(), None),
+        Some(stats) => stats,
+    };
+    let nested = RECORDING.with(Cell::get);
+    if nested {
+        return (f(), Some(stats));
+    }
+    stats.operations += 1;
+    let installed = Installed::new(stats);
+    let result = f();
+    (result, installed.finish())
+}
@@ -0,0 +1,217 @@
+use std::error::Error;
+use std::fmt;
+
+use {TreeF, TreeAlgebra, TreeNode, AnnotatedTreeNode, BitRangeNode, SplayTree, BitRange};
+use Direction;
+
+// Consistency checks for the tree types. These walk the whole tree, so they
+// are meant for tests and debugging rather than for use after every operation.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InvariantViolation {
+    OutOfOrder,
+    SizeMismatch { stored: usize, actual: usize },
+    CountMismatch { stored: usize, actual: usize },
+    AnnotationMismatch,
+}
+
+// A violation together with the path from the root to the offending node,
+// written as a string of 'L' and 'R' steps over the stored children.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantError {
+    pub path: String,
+    pub violation: InvariantViolation,
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.violation {
+            InvariantViolation::OutOfOrder => {
+                write!(f, "values out of order")?;
+            },
+            InvariantViolation::SizeMismatch { stored, actual } => {
+                write!(f, "stored size {} but subtree has {} nodes", stored, actual)?;
+            },
+            InvariantViolation::CountMismatch { stored, actual } => {
+                write!(f, "stored count of set bits {} but subtree has {}", stored, actual)?;
+            },
+            InvariantViolation::AnnotationMismatch => {
+                write!(f, "annotation does not match its subtrees")?;
+            },
+        }
+        if self.path.is_empty() {
+            write!(f, " at the root")
+        } else {
+            write!(f, " at path {}", self.path)
+        }
+    }
+}
+
+impl Error for InvariantError {}
+
+type Children<'a, N> = Option<(&'a N, &'a N)>;
+
+// The smallest and largest value in a subtree, if it is not empty.
+type Bounds<'a, A> = Option<(&'a A, &'a A)>;
+
+enum Frame<'a, N: 'a> {
+    Enter(&'a N, Option<Direction>),
+    Exit(&'a N, Option<Direction>),
+}
+
+// Folds over a tree in post-order. Splay trees can be arbitrarily deep, so
+// this keeps its own stack rather than recursing. `children` returns the
+// stored children of a branch, and `check` receives each node together with
+// the results for its children (None for empty nodes).
+fn fold_tree<'a, N, R, C, F>(root: &'a N, children: C, mut check: F) -> Result<R, InvariantError>
+    where C: Fn(&'a N) -> Children<'a, N>,
+          F: FnMut(&'a N, Option<(R, R)>) -> Result<R, InvariantViolation> {
+    let mut stack = vec![Frame::Enter(root, None)];
+    let mut results = Vec::new();
+    while let Some(frame) = stack.pop() {
+        let (node, direction, below) = match frame {
+            Frame::Enter(node, direction) => {
+                match children(node) {
+                    Some((left, right)) => {
+                        stack.push(Frame::Exit(node, direction));
+                        stack.push(Frame::Enter(right, Some(Direction::Right)));
+                        stack.push(Frame::Enter(left, Some(Direction::Left)));
+                        continue;
+                    },
+                    None => (node, direction, None),
+                }
+            },
+            Frame::Exit(node, direction) => {
+                let right = results.pop().unwrap();
+                let left = results.pop().unwrap();
+                (node, direction, Some((left, right)))
+            },
+        };
+        match check(node, below) {
+            Ok(result) => results.push(result),
+            Err(violation) => {
+                let mut path: String = stack.iter().filter_map(|frame| {
+                    match *frame {
+                        Frame::Exit(_, Some(ref d)) => Some(step_name(d)),
+                        _ => None,
+                    }
+                }).collect();
+                if let Some(ref d) = direction {
+                    path.push(step_name(d));
+                }
+                return Err(InvariantError {
+                    path: path,
+                    violation: violation,
+                });
+            },
+        }
+    }
+    Ok(results.pop().unwrap())
+}
+
+fn step_name(d: &Direction) -> char {
+    match *d {
+        Direction::Left => 'L',
+        Direction::Right => 'R',
+    }
+}
+
+fn tree_children<A>(node: &TreeNode<A>) -> Children<'_, TreeNode<A>> {
+    match *node {
+        TreeNode(TreeF::Empty) => None,
+        TreeNode(TreeF::Branch { ref left, ref right, .. }) => Some((&**left, &**right)),
+    }
+}
+
+fn bit_range_children(node: &BitRangeNode) -> Children<'_, BitRangeNode> {
+    match *node {
+        BitRangeNode::Empty => None,
+        BitRangeNode::Branch { ref left, ref right, .. } => Some((&**left, &**right)),
+    }
+}
+
+fn annotated_children<A, B>(node: &AnnotatedTreeNode<A, B>) -> Children<'_, AnnotatedTreeNode<A, B>> {
+    match node.node {
+        TreeF::Empty => None,
+        TreeF::Branch { ref left, ref right, .. } => Some((&**left, &**right)),
+    }
+}
+
+impl<A: Ord> SplayTree<A> {
+    // Checks that the values are in strictly increasing order.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        fold_tree(&self.root, tree_children, |node, below: Option<(Bounds<A>, Bounds<A>)>| {
+            let val = match *node {
+                TreeNode(TreeF::Empty) => return Ok(None),
+                TreeNode(TreeF::Branch { ref val, .. }) => val,
+            };
+            let (left, right) = below.unwrap();
+            let mut bounds = (val, val);
+            if let Some((min, max)) = left {
+                if *max >= *val {
+                    return Err(InvariantViolation::OutOfOrder);
+                }
+                bounds.0 = min;
+            }
+            if let Some((min, max)) = right {
+                if *min <= *val {
+                    return Err(InvariantViolation::OutOfOrder);
+                }
+                bounds.1 = max;
+            }
+            Ok(Some(bounds))
+        }).map(|_| ())
+    }
+}
+
+impl BitRange {
+    // Checks every cached size and count of set bits. A pending reversal only
+    // swaps the children of a node, so sizes are compared against the stored
+    // children directly. A pending flip or fill has already been applied to
+    // the node's own counts but not to its children's, so it is applied to
+    // theirs before comparing.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        fold_tree(&self.root, bit_range_children, |node, below| {
+            match (node, below) {
+                (&BitRangeNode::Branch { here, size, ones, pending, .. }, Some((left, right))) => {
+                    let (left_size, left_ones) = left;
+                    let (right_size, right_ones) = right;
+                    let actual_size = left_size + right_size + 1;
+                    if size.get() != actual_size {
+                        return Err(InvariantViolation::SizeMismatch { stored: size.get(), actual: actual_size });
+                    }
+                    let actual_ones = pending.ones(left_ones + right_ones, left_size + right_size) + here as usize;
+                    if ones.get() != actual_ones {
+                        return Err(InvariantViolation::CountMismatch { stored: ones.get(), actual: actual_ones });
+                    }
+                    Ok((actual_size, actual_ones))
+                },
+                _ => Ok((0, 0)),
+            }
+        }).map(|_| ())
+    }
+}
+
+impl<A: Clone, B: TreeAlgebra<A> + Copy + PartialEq> AnnotatedTreeNode<A, B> {
+    // Checks that every annotation is what `combine` produces from the node's
+    // value and the annotations of its children.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        fold_tree(self, annotated_children, |node, below| {
+            let expected = match node.node {
+                TreeF::Branch { ref val, .. } => {
+                    let (left, right) = below.unwrap();
+                    TreeAlgebra::combine(TreeF::Branch {
+                        val: val.clone(),
+                        left: left,
+                        right: right,
+                    })
+                },
+                TreeF::Empty => TreeAlgebra::combine(TreeF::Empty),
+            };
+            if node.annotation != expected {
+                return Err(InvariantViolation::AnnotationMismatch);
+            }
+            Ok(node.annotation)
+        }).map(|_| ())
+    }
+}
@@ -0,0 +1,391 @@
+use std::io::{self, Write};
+
+use command::{Command, Header};
+
+// Random workloads for benchmarks and end-to-end tests. Everything is driven
+// by a small seeded generator so that a workload can be reproduced from its
+// parameters alone.
+
+// Marsaglia's xorshift64. Not suitable for anything but test data.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        // The all-zero state is a fixed point, so nudge it.
+        Rng(if seed == 0 { 0x2545_f491_4f6c_dd1d } else { seed })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // A value in 0..n, for n > 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    // A value in [0, 1).
+    pub fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// How keys or indices are drawn from 0..n. Zipf(s) makes key k proportionally
+// likely to 1 / (k + 1)^s, so a few small keys get most of the accesses.
+// Sequential walks through 0..n in order, wrapping around.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    Zipf(f64),
+    Sequential,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    n: u64,
+    distribution: Distribution,
+    cdf: Vec<f64>,
+    next: u64,
+}
+
+impl Sampler {
+    pub fn new(n: u64, distribution: Distribution) -> Sampler {
+        let mut cdf = Vec::new();
+        if let Distribution::Zipf(s) = distribution {
+            let mut total = 0.0;
+            for k in 0..n {
+                total += 1.0 / ((k + 1) as f64).powf(s);
+                cdf.push(total);
+            }
+            for p in &mut cdf {
+                *p /= total;
+            }
+        }
+        Sampler {
+            n: n,
+            distribution: distribution,
+            cdf: cdf,
+            next: 0,
+        }
+    }
+
+    // A value in 0..n, or 0 if n is 0.
+    pub fn sample(&mut self, rng: &mut Rng) -> u64 {
+        if self.n == 0 {
+            return 0;
+        }
+        match self.distribution {
+            Distribution::Uniform => rng.below(self.n),
+            Distribution::Zipf(_) => {
+                // Rounding can leave the last entry of the table just below
+                // 1, so clamp to the largest key.
+                let u = rng.unit();
+                let k = self.cdf.partition_point(|&p| p <= u);
+                k.min(self.cdf.len() - 1) as u64
+            },
+            Distribution::Sequential => {
+                let k = self.next;
+                self.next = (self.next + 1) % self.n;
+                k
+            },
+        }
+    }
+}
+
+// Relative weights of the commands in a generated workload, one per command
+// of the driver's language.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Mix {
+    pub set: u32,
+    pub get: u32,
+    pub reverse: u32,
+    pub insert: u32,
+    pub delete: u32,
+    pub count: u32,
+    pub flip: u32,
+    pub fill: u32,
+    pub print: u32,
+    pub moves: u32,
+}
+
+impl Mix {
+    fn weights(&self) -> [(char, u32); 10] {
+        [
+            ('S', self.set),
+            ('G', self.get),
+            ('R', self.reverse),
+            ('I', self.insert),
+            ('D', self.delete),
+            ('C', self.count),
+            ('F', self.flip),
+            ('A', self.fill),
+            ('P', self.print),
+            ('M', self.moves),
+        ]
+    }
+
+    // Sets the weight of the command introduced by `letter`, returning false
+    // if there is no such command.
+    pub fn set_weight(&mut self, letter: char, weight: u32) -> bool {
+        let slot = match letter {
+            'S' => &mut self.set,
+            'G' => &mut self.get,
+            'R' => &mut self.reverse,
+            'I' => &mut self.insert,
+            'D' => &mut self.delete,
+            'C' => &mut self.count,
+            'F' => &mut self.flip,
+            'A' => &mut self.fill,
+            'P' => &mut self.print,
+            'M' => &mut self.moves,
+            _ => return false,
+        };
+        *slot = weight;
+        true
+    }
+
+    fn pick(&self, rng: &mut Rng) -> char {
+        // Ten `u32` weights cannot overflow a `u64` total.
+        let weights = self.weights();
+        let total: u64 = weights.iter().map(|&(_, w)| w as u64).sum();
+        assert!(total > 0, "empty command mix");
+        let mut pick = rng.below(total);
+        for &(letter, weight) in &weights {
+            if pick < weight as u64 {
+                return letter;
+            }
+            pick -= weight as u64;
+        }
+        unreachable!()
+    }
+}
+
+// Generates `m` commands against a sequence that starts with `n` bits. The
+// length is tracked as insertions and deletions change it, so that every
+// command is valid for the driver: sets, reads and deletions turn into
+// insertions while the sequence is empty. Positions are drawn from `indices` and
+// reduced modulo the current length; a range takes two draws, one of them
+// shifted past the end so that the whole sequence can be covered.
+pub fn bit_commands(rng: &mut Rng, n: usize, m: usize, mix: Mix, indices: &mut Sampler) -> Vec<Command> {
+    let mut len = n;
+    let mut commands = Vec::with_capacity(m);
+    for _ in 0..m {
+        let mut position = |rng: &mut Rng, len: usize| {
+            if len == 0 { 0 } else { (indices.sample(rng) % len as u64) as usize }
+        };
+        let mut letter = mix.pick(rng);
+        if len == 0 && (letter == 'S' || letter == 'G' || letter == 'D') {
+            letter = 'I';
+        }
+        let (start, end) = {
+            let a = position(rng, len);
+            let b = position(rng, len) + 1;
+            (a.min(b), a.max(b).min(len))
+        };
+        let command = match letter {
+            'S' => Command::Set(position(rng, len), rng.below(2) == 1),
+            'G' => Command::Get(position(rng, len)),
+            'R' => Command::Reverse(start, end),
+            'I' => {
+                len += 1;
+                Command::Insert(position(rng, len), rng.below(2) == 1)
+            },
+            'D' => {
+                let index = position(rng, len);
+                len -= 1;
+                Command::Delete(index)
+            },
+            'C' => Command::Count(start, end),
+            'F' => Command::Flip(start, end),
+            'A' => Command::Fill(start, end, rng.below(2) == 1),
+            'P' => Command::Print,
+            _ => Command::Move(start, end, position(rng, len - (end - start) + 1)),
+        };
+        commands.push(command);
+    }
+    commands
+}
+
+// Writes a workload in the driver's input format.
+pub fn write_input<W: Write>(out: &mut W, n: usize, commands: &[Command]) -> io::Result<()> {
+    let header = Header {
+        length: n,
+        commands: commands.len(),
+    };
+    writeln!(out, "{}", header)?;
+    for command in commands {
+        writeln!(out, "{}", command)?;
+    }
+    Ok(())
+}
+
+// The part of [start, end) that lies within a sequence of `len` elements,
+// clamped the same way the tree operations clamp it.
+fn clamp(len: usize, start: usize, end: usize) -> (usize, usize) {
+    let start = start.min(len);
+    let end = end.min(len);
+    (start, end.max(start))
+}
+
+fn digit(bit: bool) -> char {
+    if bit { '1' } else { '0' }
+}
+
+// Writes the output the driver should produce for a workload, computed with
+// a plain `Vec<bool>`. The driver stops at the first S, G, I or D whose index
+// is out of range, keeping the output written so far, and so does this.
+// Workloads from `bit_commands` never contain such commands.
+pub fn write_expected<W: Write>(out: &mut W, n: usize, commands: &[Command]) -> io::Result<()> {
+    let mut bits = vec![false; n];
+    for &command in commands {
+        let len = bits.len();
+        match command {
+            Command::Set(i, _) | Command::Get(i) | Command::Delete(i) if i >= len => break,
+            Command::Insert(i, _) if i > len => break,
+            _ => {},
+        }
+        match command {
+            Command::Set(i, val) => bits[i] = val,
+            Command::Get(i) => writeln!(out, "{}", digit(bits[i]))?,
+            Command::Reverse(start, end) => {
+                let (start, end) = clamp(len, start, end);
+                bits[start..end].reverse();
+            },
+            Command::Insert(i, val) => bits.insert(i, val),
+            Command::Delete(i) => {
+                bits.remove(i);
+            },
+            Command::Count(start, end) => {
+                let (start, end) = clamp(len, start, end);
+                writeln!(out, "{}", bits[start..end].iter().filter(|&&b| b).count())?;
+            },
+            Command::Flip(start, end) => {
+                let (start, end) = clamp(len, start, end);
+                for bit in &mut bits[start..end] {
+                    *bit = !*bit;
+                }
+            },
+            Command::Fill(start, end, val) => {
+                let (start, end) = clamp(len, start, end);
+                for bit in &mut bits[start..end] {
+                    *bit = val;
+                }
+            },
+            Command::Print => {
+                let line: String = bits.iter().map(|&b| digit(b)).collect();
+                writeln!(out, "{}", line)?;
+            },
+            Command::Move(start, end, to) => {
+                let (start, end) = clamp(len, start, end);
+                let block: Vec<bool> = bits.drain(start..end).collect();
+                let to = to.min(bits.len());
+                bits.splice(to..to, block);
+            },
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use command::{parse_command, parse_header};
+
+    #[test]
+    fn zipf_prefers_small_keys() {
+        let mut rng = Rng::new(7);
+        let mut sampler = Sampler::new(100, Distribution::Zipf(1.0));
+        let samples: Vec<u64> = (0..1000).map(|_| sampler.sample(&mut rng)).collect();
+        assert!(samples.iter().all(|&k| k < 100));
+        let small = samples.iter().filter(|&&k| k < 10).count();
+        assert!(small > 400, "{} of 1000 samples below 10", small);
+    }
+
+    #[test]
+    fn sequential_wraps_around() {
+        let mut rng = Rng::new(1);
+        let mut sampler = Sampler::new(3, Distribution::Sequential);
+        let samples: Vec<u64> = (0..5).map(|_| sampler.sample(&mut rng)).collect();
+        assert_eq!(samples, vec![0, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn generated_commands_stay_in_range() {
+        let mut rng = Rng::new(11);
+        let mut indices = Sampler::new(8, Distribution::Zipf(1.2));
+        let mut mix = Mix::default();
+        for letter in "SGRIDCFAPM".chars() {
+            mix.set_weight(letter, 1);
+        }
+        mix.set_weight('D', 3);
+        let mut len = 4;
+        for command in bit_commands(&mut rng, len, 500, mix, &mut indices) {
+            match command {
+                Command::Set(i, _) | Command::Get(i) | Command::Delete(i) => assert!((0..len).contains(&i), "{} with {} bits", command, len),
+                Command::Insert(i, _) => assert!((0..=len).contains(&i), "{} with {} bits", command, len),
+                Command::Move(start, end, to) => assert!(to <= len - (end - start)),
+                _ => {},
+            }
+            match command {
+                Command::Insert(..) => len += 1,
+                Command::Delete(..) => len -= 1,
+                _ => {},
+            }
+        }
+    }
+
+    #[test]
+    fn huge_weights_do_not_overflow() {
+        let mut rng = Rng::new(5);
+        let mix = Mix { set: u32::MAX, get: u32::MAX, ..Mix::default() };
+        let picks: Vec<char> = (0..100).map(|_| mix.pick(&mut rng)).collect();
+        assert!(picks.contains(&'S') && picks.contains(&'G'));
+        assert!(picks.iter().all(|&c| c == 'S' || c == 'G'));
+    }
+
+    #[test]
+    fn expected_output_follows_the_commands() {
+        let commands = [
+            Command::Fill(1, 3, true),
+            Command::Print,
+            Command::Insert(0, true),
+            Command::Count(0, 9),
+            Command::Move(0, 2, 3),
+            Command::Delete(4),
+            Command::Reverse(0, 2),
+            Command::Print,
+            Command::Get(4),
+            Command::Print,
+        ];
+        let mut out = Vec::new();
+        write_expected(&mut out, 4, &commands).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "0110\n3\n1101\n");
+    }
+
+    #[test]
+    fn generated_input_parses_back() {
+        let mut rng = Rng::new(3);
+        let mut indices = Sampler::new(10, Distribution::Uniform);
+        let mix = Mix { set: 1, get: 1, reverse: 2, ..Mix::default() };
+        let commands = bit_commands(&mut rng, 10, 50, mix, &mut indices);
+        let mut out = Vec::new();
+        write_input(&mut out, 10, &commands).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let mut lines = text.lines();
+        let header = parse_header(lines.next().unwrap()).unwrap();
+        assert_eq!((header.length, header.commands), (10, 50));
+        let parsed: Vec<Command> = lines.map(|line| parse_command(line).unwrap()).collect();
+        assert_eq!(parsed, commands);
+        for command in commands {
+            match command {
+                Command::Set(i, _) | Command::Get(i) => assert!((0..10).contains(&i)),
+                Command::Reverse(start, end) => assert!(start <= end && end <= 10),
+                _ => panic!("unexpected command {}", command),
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+extern crate rust_splay;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use rust_splay::workload::{bit_commands, write_expected, write_input, Distribution, Mix, Rng, Sampler};
+
+// End-to-end tests: generated workloads are fed to the driver binary and its
+// output is compared with that of the naive reference.
+
+fn run_driver(input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust_splay"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("could not start the driver");
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success(), "driver failed: {}", String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+fn check(seed: u64, n: usize, m: usize, mix: Mix, distribution: Distribution) {
+    let mut rng = Rng::new(seed);
+    let mut indices = Sampler::new(n.max(1) as u64, distribution);
+    let commands = bit_commands(&mut rng, n, m, mix, &mut indices);
+    let mut input = Vec::new();
+    write_input(&mut input, n, &commands).unwrap();
+    let mut expected = Vec::new();
+    write_expected(&mut expected, n, &commands).unwrap();
+    let actual = run_driver(&input);
+    assert!(actual == expected, "seed {}: driver output differs from the reference\n{}",
+            seed, String::from_utf8_lossy(&input));
+}
+
+#[test]
+fn driver_matches_reference() {
+    let mixes = [
+        Mix { set: 1, get: 1, reverse: 1, ..Mix::default() },
+        Mix { set: 1, get: 2, reverse: 1, insert: 1, delete: 1, count: 1, flip: 1, fill: 1, print: 1, moves: 1 },
+    ];
+    for (seed, &mix) in (1..).zip(mixes.iter().cycle().take(6)) {
+        check(seed, 40, 300, mix, Distribution::Uniform);
+        check(seed, 1, 100, mix, Distribution::Zipf(1.0));
+        check(seed, 0, 100, mix, Distribution::Sequential);
+    }
+}
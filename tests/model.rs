@@ -0,0 +1,641 @@
+extern crate rust_splay;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rust_splay::{BitRange, Splay, SplayPolicy, SplayStrategy, SplayTree, TreeAlgebra, TreeF};
+use rust_splay::arena::{ArenaBitRange, ArenaSplayTree};
+use rust_splay::euler_tour::EulerTourForest;
+use rust_splay::interval::SplayIntervalTree;
+use rust_splay::link_cut::LinkCutForest;
+use rust_splay::multiset::SplayMultiset;
+use rust_splay::persistent::{PersistentBitRange, PersistentSplayTree};
+use rust_splay::rope::SplayRope;
+use rust_splay::sequence::SplaySequence;
+
+// Randomized differential tests: every structure is driven with the same
+// random operations as a simple reference model and must agree with it.
+
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % (n as u64)) as usize
+    }
+
+    // An index that is usually in [0, n) but sometimes just past it.
+    fn index(&mut self, n: usize) -> usize {
+        self.below(n + 3)
+    }
+}
+
+const STRATEGIES: [SplayStrategy; 2] = [SplayStrategy::BottomUp, SplayStrategy::TopDown];
+
+const POLICIES: [SplayPolicy; 4] = [
+    SplayPolicy::Full,
+    SplayPolicy::SemiSplay,
+    SplayPolicy::DepthThreshold(3),
+    SplayPolicy::NoSplayOnRead,
+];
+
+// The part of [start, end) that lies within the model, as slice bounds.
+fn clamp_range(model: &[bool], start: usize, end: usize) -> (usize, usize) {
+    let start = start.min(model.len());
+    let end = end.min(model.len());
+    (start, end.max(start))
+}
+
+fn reverse_model(model: &mut [bool], start: usize, end: usize) {
+    let (start, end) = clamp_range(model, start, end);
+    model[start..end].reverse();
+}
+
+fn model_get(model: &[bool], index: usize) -> Option<bool> {
+    model.get(index).cloned()
+}
+
+#[test]
+fn splay_tree_matches_btreeset() {
+    for &strategy in &STRATEGIES {
+        for &policy in &POLICIES {
+            let mut rng = Rng(0x1234_5678);
+            let mut tree = SplayTree::with_strategy(strategy);
+            tree.set_policy(policy);
+            let mut model = BTreeSet::new();
+            for step in 0..5000 {
+                let v = rng.below(300);
+                match rng.below(3) {
+                    0 => {
+                        tree.insert(v);
+                        model.insert(v);
+                    },
+                    1 => assert_eq!(tree.contains(v), model.contains(&v)),
+                    _ => tree.splay_to_root(v),
+                }
+                if step % 500 == 0 {
+                    tree.validate().unwrap();
+                }
+            }
+            for v in 0..300 {
+                assert_eq!(tree.contains(v), model.contains(&v), "{:?} {:?} {}", strategy, policy, v);
+            }
+            tree.validate().unwrap();
+        }
+    }
+}
+
+#[test]
+fn splay_multiset_matches_btreemap() {
+    for &strategy in &STRATEGIES {
+        let mut rng = Rng(0x5851_f42d);
+        let mut multiset = SplayMultiset::with_strategy(strategy);
+        let mut model: BTreeMap<usize, usize> = BTreeMap::new();
+        for _ in 0..3000 {
+            let v = rng.below(30);
+            match rng.below(6) {
+                0 | 1 => {
+                    *model.entry(v).or_insert(0) += 1;
+                    assert_eq!(multiset.insert(v), model[&v]);
+                },
+                2 => {
+                    let expected = model.get(&v).cloned().unwrap_or(0);
+                    match expected {
+                        0 => {},
+                        1 => { model.remove(&v); },
+                        _ => *model.get_mut(&v).unwrap() -= 1,
+                    }
+                    assert_eq!(multiset.remove_one(&v), expected > 0);
+                },
+                3 => assert_eq!(multiset.remove_all(&v), model.remove(&v).unwrap_or(0)),
+                4 => {
+                    let expected: usize = model.range(..v).map(|(_, &count)| count).sum();
+                    assert_eq!(multiset.rank(&v), expected);
+                    assert_eq!(multiset.count(&v), model.get(&v).cloned().unwrap_or(0));
+                },
+                _ => {
+                    let len = multiset.len();
+                    let i = rng.index(len);
+                    let expected = model.iter().flat_map(|(v, &count)| (0..count).map(move |_| v)).nth(i);
+                    assert_eq!(multiset.select(i), expected);
+                },
+            }
+            assert_eq!(multiset.len(), model.values().sum::<usize>());
+        }
+        let entries: Vec<(usize, usize)> = multiset.iter().map(|(&v, count)| (v, count)).collect();
+        assert_eq!(entries, model.into_iter().collect::<Vec<_>>());
+    }
+}
+
+#[test]
+fn splay_interval_tree_matches_btreemap() {
+    for &strategy in &STRATEGIES {
+        let mut rng = Rng(0x1405_7b7e);
+        let mut tree = SplayIntervalTree::with_strategy(strategy);
+        let mut model: BTreeMap<(usize, usize), usize> = BTreeMap::new();
+        for step in 0..2000 {
+            let start = rng.below(50);
+            let end = start + rng.below(15);
+            match rng.below(4) {
+                0 | 1 => assert_eq!(tree.insert(start..end, step), model.insert((start, end), step)),
+                2 => assert_eq!(tree.remove(&(start..end)), model.remove(&(start, end))),
+                _ => {
+                    let point = rng.below(70);
+                    let found: Vec<(usize, usize, usize)> = tree.query_point(point)
+                        .map(|i| (i.start, i.end, i.value)).collect();
+                    let expected: Vec<(usize, usize, usize)> = model.iter()
+                        .filter(|&(&(s, e), _)| s <= point && point < e)
+                        .map(|(&(s, e), &v)| (s, e, v)).collect();
+                    assert_eq!(found, expected);
+                    let found: Vec<(usize, usize, usize)> = tree.query_overlapping(start..end)
+                        .map(|i| (i.start, i.end, i.value)).collect();
+                    let expected: Vec<(usize, usize, usize)> = model.iter()
+                        .filter(|&(&(s, e), _)| s < end && start < e && start < end)
+                        .map(|(&(s, e), &v)| (s, e, v)).collect();
+                    assert_eq!(found, expected);
+                },
+            }
+            assert_eq!(tree.len(), model.len());
+        }
+        tree.root().validate().unwrap();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sum(u64);
+
+impl TreeAlgebra<u64> for Sum {
+    fn combine(input: TreeF<u64, Sum>) -> Sum {
+        match input {
+            TreeF::Empty => Sum(0),
+            TreeF::Branch { val, left, right } => Sum(left.0 + val + right.0),
+        }
+    }
+}
+
+// The nodes on the path from `u` to `v` in a forest given by adjacency lists.
+fn forest_path(edges: &[Vec<usize>], u: usize, v: usize) -> Option<Vec<usize>> {
+    let mut from = vec![None; edges.len()];
+    let mut queue = vec![u];
+    from[u] = Some(u);
+    while let Some(x) = queue.pop() {
+        for &y in &edges[x] {
+            if from[y].is_none() {
+                from[y] = Some(x);
+                queue.push(y);
+            }
+        }
+    }
+    from[v]?;
+    let mut path = vec![v];
+    while *path.last().unwrap() != u {
+        path.push(from[*path.last().unwrap()].unwrap());
+    }
+    Some(path)
+}
+
+#[test]
+fn link_cut_forest_matches_adjacency_lists() {
+    let mut rng = Rng(0x6a09_e667);
+    for &n in &[1, 2, 8, 25, 60] {
+        let mut forest: LinkCutForest<u64, Sum> = LinkCutForest::new();
+        let mut values: Vec<u64> = (0..n as u64).map(|i| i * 7 % 11).collect();
+        for &val in &values {
+            forest.add_node(val);
+        }
+        let mut edges = vec![Vec::new(); n];
+        for _ in 0..3000 {
+            let (u, v) = (rng.below(n), rng.below(n));
+            match rng.below(5) {
+                0 | 1 => {
+                    let linked = forest_path(&edges, u, v).is_none();
+                    if linked {
+                        edges[u].push(v);
+                        edges[v].push(u);
+                    }
+                    assert_eq!(forest.link(u, v), linked);
+                },
+                2 => {
+                    let cut = edges[u].contains(&v);
+                    if cut {
+                        edges[u].retain(|&x| x != v);
+                        edges[v].retain(|&x| x != u);
+                    }
+                    assert_eq!(forest.cut(u, v), cut);
+                },
+                3 => {
+                    values[u] = rng.below(100) as u64;
+                    forest.set_value(u, values[u]);
+                },
+                _ => {
+                    let path = forest_path(&edges, u, v);
+                    assert_eq!(forest.connected(u, v), path.is_some());
+                    let expected = path.as_ref().map(|path| Sum(path.iter().map(|&x| values[x]).sum()));
+                    assert_eq!(forest.path_aggregate(u, v), expected);
+                    if rng.below(2) == 0 {
+                        // The path runs from `v` to `u`, so once `v` is the
+                        // root u's parent is the step before it.
+                        forest.evert(v);
+                        assert_eq!(forest.find_root(u) == v, expected.is_some());
+                        if let Some(path) = path {
+                            assert_eq!(forest.parent(u), path.len().checked_sub(2).map(|i| path[i]));
+                        }
+                    }
+                },
+            }
+        }
+    }
+}
+
+// The number of vertices reachable from `v` without crossing the edge to `u`.
+fn side_size(edges: &[Vec<usize>], u: usize, v: usize) -> usize {
+    let mut seen = vec![false; edges.len()];
+    seen[u] = true;
+    seen[v] = true;
+    let mut stack = vec![v];
+    let mut count = 0;
+    while let Some(x) = stack.pop() {
+        count += 1;
+        for &y in &edges[x] {
+            if !seen[y] {
+                seen[y] = true;
+                stack.push(y);
+            }
+        }
+    }
+    count
+}
+
+#[test]
+fn euler_tour_forest_matches_adjacency_lists() {
+    let mut rng = Rng(0xbb67_ae85);
+    let n = 30;
+    let mut forest = EulerTourForest::new(n);
+    let mut edges = vec![Vec::new(); n];
+    for _ in 0..4000 {
+        let (u, v) = (rng.below(n), rng.below(n));
+        match rng.below(4) {
+            0 => {
+                let linked = forest_path(&edges, u, v).is_none();
+                if linked {
+                    edges[u].push(v);
+                    edges[v].push(u);
+                }
+                assert_eq!(forest.link(u, v), linked);
+            },
+            1 => {
+                let cut = edges[u].contains(&v);
+                if cut {
+                    edges[u].retain(|&x| x != v);
+                    edges[v].retain(|&x| x != u);
+                }
+                assert_eq!(forest.cut(u, v), cut);
+            },
+            2 => {
+                assert_eq!(forest.connected(u, v), forest_path(&edges, u, v).is_some());
+                let size = (0..n).filter(|&x| forest_path(&edges, u, x).is_some()).count();
+                assert_eq!(forest.tree_size(u), size);
+            },
+            _ => {
+                // Query across an existing edge if u has one.
+                let v = if edges[u].is_empty() { v } else { edges[u][rng.below(edges[u].len())] };
+                let expected = if edges[u].contains(&v) { Some(side_size(&edges, u, v)) } else { None };
+                assert_eq!(forest.subtree_size(u, v), expected);
+            },
+        }
+        assert_eq!(forest.edge_count(), edges.iter().map(|e| e.len()).sum::<usize>() / 2);
+    }
+}
+
+#[test]
+fn bit_range_matches_vec() {
+    for &strategy in &STRATEGIES {
+        for &policy in &POLICIES {
+            let mut rng = Rng(0x9e37_79b9);
+            for _ in 0..20 {
+                let n = rng.below(40);
+                let mut range = BitRange::with_strategy(n, strategy);
+                range.set_policy(policy);
+                let mut model = vec![false; n];
+                for _ in 0..400 {
+                    let n = model.len();
+                    match rng.below(10) {
+                        0 => {
+                            let i = rng.index(n);
+                            let val = rng.below(2) == 1;
+                            range.set(i, val);
+                            if i < n {
+                                model[i] = val;
+                            }
+                        },
+                        1 => {
+                            let i = rng.index(n);
+                            assert_eq!(range.get(i), model_get(&model, i), "{:?} {:?}", strategy, policy);
+                        },
+                        2 => {
+                            let i = rng.index(n);
+                            let val = rng.below(2) == 1;
+                            range.insert(i, val);
+                            model.insert(i.min(n), val);
+                        },
+                        3 => {
+                            let i = rng.index(n);
+                            let expected = model_get(&model, i);
+                            if expected.is_some() {
+                                model.remove(i);
+                            }
+                            assert_eq!(range.remove(i), expected);
+                        },
+                        4 => {
+                            let (start, end) = (rng.index(n), rng.index(n));
+                            let (lo, hi) = clamp_range(&model, start, end);
+                            let expected = model[lo..hi].iter().filter(|&&b| b).count();
+                            assert_eq!(range.count_ones(start, end), expected);
+                        },
+                        5 => {
+                            let (start, end) = (rng.index(n), rng.index(n));
+                            let (lo, hi) = clamp_range(&model, start, end);
+                            for bit in &mut model[lo..hi] {
+                                *bit = !*bit;
+                            }
+                            range.flip_range(start, end);
+                        },
+                        6 => {
+                            let (start, end) = (rng.index(n), rng.index(n));
+                            let val = rng.below(2) == 1;
+                            let (lo, hi) = clamp_range(&model, start, end);
+                            for bit in &mut model[lo..hi] {
+                                *bit = val;
+                            }
+                            range.fill_range(start, end, val);
+                        },
+                        7 => {
+                            let (start, end, to) = (rng.index(n), rng.index(n), rng.index(n));
+                            let (lo, hi) = clamp_range(&model, start, end);
+                            let block: Vec<bool> = model.drain(lo..hi).collect();
+                            let at = to.min(model.len());
+                            for (k, bit) in block.into_iter().enumerate() {
+                                model.insert(at + k, bit);
+                            }
+                            range.move_range(start, end, to);
+                        },
+                        8 => assert_eq!(range.to_vec(), model),
+                        _ => {
+                            let start = rng.index(n);
+                            let end = rng.index(n);
+                            range.reverse_range(start, end);
+                            reverse_model(&mut model, start, end);
+                        },
+                    }
+                    assert_eq!(range.len(), model.len());
+                }
+                range.validate().unwrap();
+                assert_eq!(range.to_vec(), model);
+                for i in 0..model.len() {
+                    assert_eq!(range.get(i), model_get(&model, i));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn splay_sequence_matches_vec() {
+    for &strategy in &STRATEGIES {
+        let mut rng = Rng(0x2545_f491);
+        for _ in 0..20 {
+            let mut sequence = SplaySequence::with_strategy(strategy);
+            let mut model: Vec<usize> = Vec::new();
+            for step in 0..400 {
+                let n = model.len();
+                match rng.below(8) {
+                    0 => {
+                        let i = rng.index(n);
+                        sequence.insert(i, step);
+                        model.insert(i.min(n), step);
+                    },
+                    1 => {
+                        let i = rng.index(n);
+                        let expected = if i < n { Some(model.remove(i)) } else { None };
+                        assert_eq!(sequence.remove(i), expected);
+                    },
+                    2 => {
+                        let i = rng.index(n);
+                        assert_eq!(sequence.get(i), model.get(i));
+                        if i < n {
+                            assert_eq!(sequence[i], model[i]);
+                        }
+                    },
+                    3 => {
+                        let (a, b) = (rng.index(n), rng.index(n));
+                        if a < n && b < n {
+                            model.swap(a, b);
+                            assert!(sequence.swap(a, b).is_ok());
+                        } else {
+                            assert!(sequence.swap(a, b).is_err());
+                        }
+                    },
+                    4 => {
+                        let extra = vec![step; rng.below(4)];
+                        model.extend(extra.iter().cloned());
+                        sequence.extend(extra);
+                    },
+                    5 => {
+                        let target = rng.below(400);
+                        assert_eq!(sequence.position(|&x| x == target), model.iter().position(|&x| x == target));
+                    },
+                    _ => {
+                        let (start, end) = (rng.index(n), rng.index(n));
+                        if start <= end && end <= n {
+                            model[start..end].reverse();
+                            assert!(sequence.reverse(start..end).is_ok());
+                        } else {
+                            assert!(sequence.reverse(start..end).is_err());
+                        }
+                    },
+                }
+                assert_eq!(sequence.len(), model.len());
+            }
+            assert_eq!(sequence.to_vec(), model);
+            assert_eq!(sequence.first(), model.first());
+            assert_eq!(sequence.last(), model.last());
+        }
+    }
+}
+
+#[test]
+fn splay_rope_matches_vec_of_chars() {
+    let alphabet: Vec<char> = "ab\nçé✓ 𝄞".chars().collect();
+    for &strategy in &STRATEGIES {
+        let mut rng = Rng(0x3c6e_f372);
+        let mut rope = SplayRope::with_strategy(strategy);
+        let mut model: Vec<char> = Vec::new();
+        for _ in 0..1500 {
+            let n = model.len();
+            let (start, end) = (rng.below(n + 1), rng.below(n + 1));
+            let (start, end) = (start.min(end), start.max(end));
+            match rng.below(6) {
+                0 | 1 => {
+                    let len = if rng.below(10) == 0 { 300 } else { rng.below(4) };
+                    let text: String = (0..len).map(|_| alphabet[rng.below(alphabet.len())]).collect();
+                    rope.insert(start, &text).unwrap();
+                    model.splice(start..start, text.chars());
+                },
+                2 => {
+                    if rng.below(3) == 0 {
+                        rope.remove(start..end).unwrap();
+                        model.drain(start..end);
+                    }
+                },
+                3 => {
+                    rope.reverse(start..end).unwrap();
+                    model[start..end].reverse();
+                },
+                4 => {
+                    let expected: String = model[start..end].iter().collect();
+                    assert_eq!(rope.slice(start..end), Ok(expected));
+                },
+                _ => {
+                    let text: String = model.iter().collect();
+                    let lines: Vec<&str> = text.split('\n').collect();
+                    let line = rng.below(lines.len() + 1);
+                    assert_eq!(rope.line(line).as_deref(), lines.get(line).cloned());
+                    let before = model[..start].iter().filter(|&&c| c == '\n').count();
+                    assert_eq!(rope.char_to_line(start), Ok(before));
+                },
+            }
+            assert_eq!(rope.len_chars(), model.len());
+        }
+        let text: String = model.iter().collect();
+        assert_eq!(rope.to_string(), text);
+        assert_eq!(rope.len_bytes(), text.len());
+        assert_eq!(rope.len_lines(), text.split('\n').count());
+    }
+}
+
+#[test]
+fn arena_splay_tree_matches_btreeset() {
+    let mut rng = Rng(0xdead_beef);
+    let mut tree = ArenaSplayTree::new();
+    let mut model = BTreeSet::new();
+    for _ in 0..10000 {
+        let v = rng.below(300);
+        match rng.below(3) {
+            0 => {
+                tree.insert(v);
+                model.insert(v);
+            },
+            1 => assert_eq!(tree.remove(&v), model.remove(&v)),
+            _ => assert_eq!(tree.contains(v), model.contains(&v)),
+        }
+        assert_eq!(tree.len(), model.len());
+    }
+}
+
+#[test]
+fn arena_bit_range_matches_vec() {
+    let mut rng = Rng(0x0bad_cafe);
+    for _ in 0..20 {
+        let n = rng.below(40);
+        let mut range = ArenaBitRange::new(n);
+        let mut model = vec![false; n];
+        for _ in 0..400 {
+            match rng.below(3) {
+                0 => {
+                    let i = rng.index(n);
+                    let val = rng.below(2) == 1;
+                    range.set(i, val);
+                    if i < n {
+                        model[i] = val;
+                    }
+                },
+                1 => {
+                    let i = rng.index(n);
+                    assert_eq!(range.get(i), model_get(&model, i));
+                },
+                _ => {
+                    let start = rng.index(n);
+                    let end = rng.index(n);
+                    range.reverse_range(start, end);
+                    reverse_model(&mut model, start, end);
+                },
+            }
+        }
+    }
+}
+
+#[test]
+fn persistent_versions_are_independent() {
+    let mut rng = Rng(0x5eed);
+    let mut versions = vec![(PersistentBitRange::new(10), vec![false; 10])];
+    for _ in 0..1000 {
+        let (range, model) = versions[rng.below(versions.len())].clone();
+        let n = model.len();
+        let mut next = model.clone();
+        let range = match rng.below(4) {
+            0 => {
+                let i = rng.index(n);
+                let val = rng.below(2) == 1;
+                if i < n {
+                    next[i] = val;
+                }
+                range.set(i, val)
+            },
+            1 => {
+                let i = rng.below(n + 1);
+                let val = rng.below(2) == 1;
+                next.insert(i, val);
+                range.insert(i, val)
+            },
+            2 => {
+                let i = rng.index(n);
+                if i < n {
+                    next.remove(i);
+                }
+                range.remove(i)
+            },
+            _ => {
+                let start = rng.index(n);
+                let end = rng.index(n);
+                reverse_model(&mut next, start, end);
+                range.reverse_range(start, end)
+            },
+        };
+        versions.push((range, next));
+        if versions.len() > 20 {
+            versions.remove(0);
+        }
+        for (range, model) in &versions {
+            assert_eq!(range.len(), model.len());
+            for (i, &val) in model.iter().enumerate() {
+                assert_eq!(range.get(i), Some(val));
+            }
+        }
+    }
+
+    let mut tree = PersistentSplayTree::new();
+    let mut model = BTreeSet::new();
+    let mut snapshots = Vec::new();
+    for _ in 0..2000 {
+        let v = rng.below(100);
+        if rng.below(2) == 0 {
+            tree = tree.insert(v);
+            model.insert(v);
+        } else {
+            tree = tree.remove(&v);
+            model.remove(&v);
+        }
+        snapshots.push((tree.clone(), model.clone()));
+    }
+    for (tree, model) in snapshots {
+        assert_eq!(tree.len(), model.len());
+        for v in 0..100 {
+            assert_eq!(tree.contains(&v), model.contains(&v));
+        }
+    }
+}